@@ -2,8 +2,16 @@ use game_tree::{GameTreeNode, NodeState};
 use game_tree_strategy::strategies::random::RandomStrategy;
 use game_tree_strategy::strategies::search_tree::LearningSearchTreeStrategy;
 use game_tree_strategy::Strategy;
+use monte_carlo_tree_search::{
+    Backup, Puct, SearchTree, SelectionPolicy, Thompson, Ucb1, Ucb1Tuned, UcbV,
+};
+use rand::distributions::{Distribution, WeightedIndex};
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::Arc;
 use structopt::{self, StructOpt};
 use strum_macros::EnumString;
 
@@ -18,6 +26,26 @@ struct Opt {
 
     #[structopt(short = "g", long = "game")]
     pub game: GameType,
+
+    /// Selection policies to compare, each is played as a separate strategy.
+    #[structopt(long = "selection", default_value = "UCB1")]
+    pub selections: Vec<SelectionType>,
+
+    /// How rewards are backed up in games with more than two players.
+    #[structopt(long = "backup", default_value = "MaxN")]
+    pub backup: BackupType,
+
+    /// Drop search statistics which are unreachable from the current position.
+    #[structopt(long = "prune-tree")]
+    pub prune_tree: bool,
+
+    /// Warm-start the search tree with statistics saved by `--save-tree`.
+    #[structopt(long = "load-tree", parse(from_os_str))]
+    pub load_tree: Option<PathBuf>,
+
+    /// Save the statistics of the search tree once all games are played.
+    #[structopt(long = "save-tree", parse(from_os_str))]
+    pub save_tree: Option<PathBuf>,
 }
 
 #[derive(Debug, EnumString)]
@@ -26,18 +54,62 @@ enum StrategyType {
     MonteCarloSearchTree,
 }
 
+#[derive(Debug, EnumString)]
+enum SelectionType {
+    #[strum(serialize = "UCB1")]
+    Ucb1,
+    #[strum(serialize = "UCB1-Tuned")]
+    Ucb1Tuned,
+    #[strum(serialize = "UCB-V")]
+    UcbV,
+    #[strum(serialize = "PUCT")]
+    Puct,
+    Thompson,
+}
+
+impl SelectionType {
+    fn policy(&self) -> Arc<dyn SelectionPolicy> {
+        match self {
+            SelectionType::Ucb1 => Arc::new(Ucb1),
+            SelectionType::Ucb1Tuned => Arc::new(Ucb1Tuned),
+            SelectionType::UcbV => Arc::new(UcbV),
+            SelectionType::Puct => Arc::new(Puct),
+            SelectionType::Thompson => Arc::new(Thompson),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, EnumString)]
+enum BackupType {
+    MaxN,
+    Paranoid,
+}
+
+impl From<BackupType> for Backup {
+    fn from(backup: BackupType) -> Self {
+        match backup {
+            BackupType::MaxN => Backup::MaxN,
+            BackupType::Paranoid => Backup::Paranoid,
+        }
+    }
+}
+
 #[derive(Debug, EnumString)]
 enum GameType {
     TicTacToe,
+    /// Nim with piles of 3, 4 and 5 objects between 3 players.
+    Nim,
+    /// Pig played to 30 points.
+    Pig,
 }
 
 fn main() {
     let opt = Opt::from_args();
-    let root_node = match opt.game {
-        GameType::TicTacToe => game_tree::games::tic_tac_toe::TicTacToeState::new(),
-    };
-
-    main_ty(opt, root_node);
+    match opt.game {
+        GameType::TicTacToe => main_ty(opt, game_tree::games::tic_tac_toe::TicTacToeState::new()),
+        GameType::Nim => main_ty(opt, game_tree::games::nim::NimState::new(vec![3, 4, 5], 3)),
+        GameType::Pig => main_ty(opt, game_tree::games::pig::PigState::new(30)),
+    }
 }
 
 fn main_ty<N: GameTreeNode<Node = N> + 'static>(opt: Opt, root_node: N) {
@@ -45,45 +117,87 @@ fn main_ty<N: GameTreeNode<Node = N> + 'static>(opt: Opt, root_node: N) {
         StrategyType::MonteCarloSearchTree => {
             let iterations_per_search = 1_000;
             let exploration_factor = 2.0_f64.sqrt();
-            let strategies = vec![LearningSearchTreeStrategy::new(
-                root_node.clone(),
-                iterations_per_search,
-                exploration_factor,
-            )];
+            let game = format!("{:?}", opt.game);
+            assert!(
+                opt.save_tree.is_none() || opt.selections.len() == 1,
+                "--save-tree is only supported with a single --selection"
+            );
+
+            let strategies = opt
+                .selections
+                .iter()
+                .map(|selection| {
+                    let mut strategy = LearningSearchTreeStrategy::new(
+                        root_node.clone(),
+                        iterations_per_search,
+                        exploration_factor,
+                    )
+                    .with_selection(selection.policy())
+                    .with_backup(opt.backup.into())
+                    .with_tree_pruning(opt.prune_tree);
+                    // Loaded separately so that the strategies don't share statistics.
+                    if let Some(path) = &opt.load_tree {
+                        let file = File::open(path).expect("failed to open search tree");
+                        let tree = SearchTree::load(&game, BufReader::new(file))
+                            .expect("failed to load search tree");
+                        strategy = strategy.with_search_tree(tree);
+                    }
+                    strategy
+                })
+                .collect::<Vec<_>>();
+
+            run_games(root_node, &strategies);
 
-            run_games(root_node, strategies);
+            if let Some(path) = &opt.save_tree {
+                let file = File::create(path).expect("failed to create search tree file");
+                strategies[0]
+                    .search_tree()
+                    .save(&game, BufWriter::new(file))
+                    .expect("failed to save search tree");
+            }
         }
     }
 }
 
 fn run_games<N: GameTreeNode<Node = N> + 'static, S: fmt::Display + Strategy<N>>(
     root_node: N,
-    strategies: Vec<S>,
+    strategies: &[S],
 ) {
     let random_strategy = RandomStrategy;
     for strategy in strategies {
         let mut total_reward = 0;
         let mut reward_counts = HashMap::new();
 
-        // Play games against random strategy
+        // Play games against random strategy, which plays every other player
         for _ in 0..100 {
             let mut current = root_node.clone();
 
             loop {
-                match current.calculate_state() {
-                    NodeState::Reward(reward) => {
-                        total_reward += reward;
-                        *reward_counts.entry(reward).or_insert(0) += 1;
-                        break;
-                    }
-                    NodeState::HasChildren(children) => {
-                        current = if current.is_self_turn() {
-                            strategy.select_child(current, children)
-                        } else {
-                            random_strategy.select_child(current, children)
-                        };
+                if let Some(rewards) = current.reward() {
+                    // The reward of the self player.
+                    let reward = rewards[0];
+                    total_reward += reward;
+                    *reward_counts.entry(reward).or_insert(0) += 1;
+                    break;
+                }
+
+                // Chance nodes never generate children lazily, nobody chooses
+                // the outcome of a chance node so it is sampled.
+                if !current.generates_children_lazily() {
+                    if let NodeState::Chance(mut outcomes) = current.calculate_state() {
+                        let index = WeightedIndex::new(outcomes.iter().map(|(_, weight)| weight))
+                            .expect("outcomes have valid weights")
+                            .sample(&mut rand::thread_rng());
+                        current = outcomes.swap_remove(index).0;
+                        continue;
                     }
                 }
+
+                current = if current.is_self_turn() {
+                    strategy.select_child_of(current)
+                } else {
+                    random_strategy.select_child_of(current)
+                };
             }
         }
 
@@ -1,15 +1,29 @@
-use game_tree::GameTreeNode;
+use game_tree::{GameTreeNode, NodeState};
 use rand::seq::IteratorRandom;
+use rand::Rng;
 
 use crate::Strategy;
 
 pub struct RandomStrategy;
 
-impl<N: GameTreeNode> Strategy<N> for RandomStrategy {
+impl<N: GameTreeNode<Node = N>> Strategy<N> for RandomStrategy {
     fn select_child(&self, _parent: N, children: Vec<N>) -> N {
         children
             .into_iter()
             .choose(&mut rand::thread_rng())
             .expect("array is not empty")
     }
+
+    fn select_child_of(&self, parent: N) -> N {
+        if parent.generates_children_lazily() {
+            let index = rand::thread_rng().gen_range(0, parent.number_of_children());
+            return parent.child(index);
+        }
+
+        match parent.calculate_state() {
+            NodeState::HasChildren(children) => self.select_child(parent, children),
+            NodeState::Reward(_) => panic!("parent is terminal"),
+            NodeState::Chance(_) => panic!("outcomes of chance nodes are sampled, not selected"),
+        }
+    }
 }
@@ -1,6 +1,9 @@
 use game_tree::GameTreeNode;
-use monte_carlo_tree_search::{SearchConfig, SearchTree};
+use monte_carlo_tree_search::{
+    Backup, SearchConfig, SearchTree, SelectionPolicy, TimeControl, TimeManager, Ucb1,
+};
 use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::Strategy;
@@ -11,6 +14,15 @@ pub struct LearningSearchTreeStrategy<N> {
 
     iterations_per_select: u64,
     exploration_factor: f64,
+    selection: Arc<dyn SelectionPolicy>,
+    backup: Backup,
+
+    /// Drop the statistics of nodes which are not reachable from
+    /// the parent before each search, instead of learning across games.
+    prune_tree: bool,
+
+    /// Search each move for its share of the clock instead of a fixed duration.
+    time_manager: Option<Mutex<TimeManager>>,
 }
 
 impl<N> LearningSearchTreeStrategy<N> {
@@ -21,16 +33,68 @@ impl<N> LearningSearchTreeStrategy<N> {
 
             iterations_per_select,
             exploration_factor,
+            selection: Arc::new(Ucb1),
+            backup: Backup::MaxN,
+
+            prune_tree: false,
+
+            time_manager: None,
         }
     }
+
+    pub fn with_selection(mut self, selection: Arc<dyn SelectionPolicy>) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// How rewards are backed up in games with more than two players.
+    pub fn with_backup(mut self, backup: Backup) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    pub fn with_tree_pruning(mut self, prune_tree: bool) -> Self {
+        self.prune_tree = prune_tree;
+        self
+    }
+
+    /// Play under `time_control`, the clock is shared by every move the strategy
+    /// makes until it is replaced with `set_time_control`.
+    pub fn with_time_control(mut self, time_control: TimeControl) -> Self {
+        self.time_manager = Some(Mutex::new(TimeManager::new(time_control)));
+        self
+    }
+
+    /// Replace the clock, for example when a new game starts.
+    pub fn set_time_control(&self, time_control: TimeControl) {
+        if let Some(time_manager) = &self.time_manager {
+            time_manager
+                .lock()
+                .expect("time manager is not poisoned")
+                .set_control(time_control);
+        }
+    }
+
+    /// Start from the statistics of `tree` (such as one loaded with
+    /// `SearchTree::load`) instead of an empty tree.
+    pub fn with_search_tree(mut self, tree: SearchTree) -> Self {
+        self.tree = tree;
+        self
+    }
+
+    pub fn search_tree(&self) -> &SearchTree {
+        &self.tree
+    }
 }
 
 impl<N: GameTreeNode<Node = N> + 'static> fmt::Display for LearningSearchTreeStrategy<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "MCSearchTree(explore_f={}, fully={})",
+            "MCSearchTree(explore_f={}, selection={:?}, backup={:?}, fully={})",
             self.exploration_factor,
+            self.selection,
+            self.backup,
             self.tree.number_of_fully_expanded_nodes(self.root.clone())
         )
     }
@@ -38,15 +102,30 @@ impl<N: GameTreeNode<Node = N> + 'static> fmt::Display for LearningSearchTreeStr
 
 impl<N: GameTreeNode<Node = N> + 'static> Strategy<N> for LearningSearchTreeStrategy<N> {
     fn select_child(&self, parent: N, children: Vec<N>) -> N {
-        self.tree.search(
-            parent.clone(),
-            SearchConfig {
-                max_duration: Duration::from_secs(5),
-                max_iterations: self.iterations_per_select,
-
-                exploration_factor: self.exploration_factor,
-            },
-        );
+        if self.prune_tree {
+            self.tree.advance_root(&parent);
+        }
+
+        let config = SearchConfig {
+            max_duration: Duration::from_secs(5),
+            max_iterations: self.iterations_per_select,
+
+            exploration_factor: self.exploration_factor,
+            selection: Arc::clone(&self.selection),
+            backup: self.backup,
+            ..SearchConfig::default()
+        };
+        match &self.time_manager {
+            Some(time_manager) => {
+                time_manager
+                    .lock()
+                    .expect("time manager is not poisoned")
+                    .search(&self.tree, parent.clone(), config);
+            }
+            None => {
+                self.tree.search(parent.clone(), config);
+            }
+        }
 
         let (_number_of_visits, child) = self.tree.select_most_visited_child(children);
 
@@ -1,5 +1,16 @@
-use game_tree::GameTreeNode;
+use game_tree::{GameTreeNode, NodeState};
 
-pub trait Strategy<N: GameTreeNode> {
+pub trait Strategy<N: GameTreeNode<Node = N>> {
     fn select_child(&self, parent: N, children: Vec<N>) -> N;
+
+    /// Select a child of `parent` (which is neither terminal nor a chance node), generating
+    /// its children with `calculate_state`. Strategies which don't need every child should
+    /// override this.
+    fn select_child_of(&self, parent: N) -> N {
+        match parent.calculate_state() {
+            NodeState::HasChildren(children) => self.select_child(parent, children),
+            NodeState::Reward(_) => panic!("parent is terminal"),
+            NodeState::Chance(_) => panic!("outcomes of chance nodes are sampled, not selected"),
+        }
+    }
 }
@@ -1,15 +1,96 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::hash::Hash;
 
 #[derive(Debug)]
 pub enum NodeState<N> {
     HasChildren(Vec<N>),
-    Reward(i64),
+    /// The reward of each player, indexed by player.
+    Reward(Vec<i64>),
+    /// A random event such as a dice roll, each outcome happens with a probability
+    /// proportional to its weight. Outcomes are treated as moves made by the player
+    /// who moved to the chance node, so its `current_player` is not used.
+    Chance(Vec<(N, f64)>),
+}
+
+/// A move which can be played from a node, displayed in the game's move notation.
+pub trait GameAction: Debug + Display + Clone + PartialEq + Send + Sync {
+    /// A stable index of the action in the game's fixed-size action space,
+    /// less than `GameTreeNode::ACTION_SPACE_SIZE`. The same action always has
+    /// the same index, such as the entry for it in a policy vector.
+    fn index(&self) -> usize;
 }
 
 pub trait GameTreeNode: Debug + Hash + Clone + Send + Sync {
     type Node: GameTreeNode;
+    type Action: GameAction;
+
+    /// The number of actions in the game, including those which aren't legal
+    /// from every node.
+    const ACTION_SPACE_SIZE: usize;
+
+    /// The index of the player whose turn it is, less than `number_of_players`.
+    /// Player 0 is the 'self' player.
+    fn current_player(&self) -> usize;
+
+    fn number_of_players(&self) -> usize {
+        2
+    }
+
+    fn is_self_turn(&self) -> bool {
+        self.current_player() == 0
+    }
 
-    fn is_self_turn(&self) -> bool;
     fn calculate_state(&self) -> NodeState<Self::Node>;
+
+    /// The actions which can be played from this node, empty if it is terminal or a chance node.
+    ///
+    /// In the same order as the children from `calculate_state`, so that `child(i)` is
+    /// the node after playing `legal_actions()[i]`. Searches identify the action leading
+    /// to each child by this order, and the same action from different nodes by its
+    /// `GameAction::index`.
+    fn legal_actions(&self) -> Vec<Self::Action>;
+
+    /// The node after playing `action`, which must be one of the legal actions.
+    ///
+    /// The same node as the child at the index of `action` in `legal_actions`,
+    /// games usually generate their children from `legal_actions` and `apply`.
+    fn apply(&self, action: &Self::Action) -> Self::Node;
+
+    /// Whether `reward`, `number_of_children` and `child` are implemented directly
+    /// instead of through `calculate_state`. When they are, children can be generated
+    /// one at a time (for example by random rollouts) instead of all at once.
+    ///
+    /// Should be false for chance nodes, whose outcomes aren't equally likely.
+    fn generates_children_lazily(&self) -> bool {
+        false
+    }
+
+    /// The rewards of the node if it is terminal, `None` if it has children.
+    fn reward(&self) -> Option<Vec<i64>> {
+        match self.calculate_state() {
+            NodeState::Reward(rewards) => Some(rewards),
+            NodeState::HasChildren(_) | NodeState::Chance(_) => None,
+        }
+    }
+
+    /// The number of children (or outcomes) of the node, 0 if it is terminal.
+    fn number_of_children(&self) -> usize {
+        match self.calculate_state() {
+            NodeState::Reward(_) => 0,
+            NodeState::HasChildren(children) => children.len(),
+            NodeState::Chance(outcomes) => outcomes.len(),
+        }
+    }
+
+    /// The child at `index`, in the same order as the children from `calculate_state`
+    /// (and `legal_actions`).
+    ///
+    /// Panics if `index` is not less than `number_of_children`.
+    fn child(&self, index: usize) -> Self::Node {
+        match self.calculate_state() {
+            NodeState::Reward(_) => panic!("terminal node has no children"),
+            NodeState::HasChildren(mut children) => children.swap_remove(index),
+            NodeState::Chance(mut outcomes) => outcomes.swap_remove(index).0,
+        }
+    }
 }
@@ -1 +1,3 @@
+pub mod nim;
+pub mod pig;
 pub mod tic_tac_toe;
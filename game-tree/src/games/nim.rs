@@ -0,0 +1,192 @@
+use crate::{GameAction, GameTreeNode, NodeState};
+use std::fmt;
+use std::str::FromStr;
+
+/// Taking `count` objects from a pile, written as the pile (counting from 1)
+/// and the count, so "2:3" takes 3 objects from the second pile.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct NimAction {
+    pile: usize,
+    count: u32,
+}
+
+impl NimAction {
+    pub fn new(pile: usize, count: u32) -> Self {
+        assert!(
+            pile < NimState::MAX_PILES,
+            "pile must be less than {}",
+            NimState::MAX_PILES
+        );
+        assert!(
+            (1..=NimState::MAX_PILE_SIZE).contains(&count),
+            "count must be between 1 and {}",
+            NimState::MAX_PILE_SIZE
+        );
+        NimAction { pile, count }
+    }
+}
+
+impl GameAction for NimAction {
+    fn index(&self) -> usize {
+        self.pile * NimState::MAX_PILE_SIZE as usize + (self.count - 1) as usize
+    }
+}
+
+impl fmt::Display for NimAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.pile + 1, self.count)
+    }
+}
+
+impl FromStr for NimAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected a pile and count such as '2:3', got '{}'", s);
+        let mut parts = s.splitn(2, ':');
+        let pile = parts
+            .next()
+            .and_then(|pile| pile.parse::<usize>().ok())
+            .filter(|pile| (1..=NimState::MAX_PILES).contains(pile))
+            .ok_or_else(error)?;
+        let count = parts
+            .next()
+            .and_then(|count| count.parse::<u32>().ok())
+            .filter(|count| (1..=NimState::MAX_PILE_SIZE).contains(count))
+            .ok_or_else(error)?;
+        Ok(NimAction {
+            pile: pile - 1,
+            count,
+        })
+    }
+}
+
+/// Nim for any number of players, who take turns removing any number of objects
+/// from one pile. The player taking the last object wins with a reward of 1,
+/// every other player loses with a reward of -1.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct NimState {
+    piles: Vec<u32>,
+    number_of_players: usize,
+
+    /// The player whose turn it is.
+    current_player: usize,
+}
+
+impl NimState {
+    pub const MAX_PILES: usize = 8;
+    pub const MAX_PILE_SIZE: u32 = 16;
+
+    pub fn new(piles: Vec<u32>, number_of_players: usize) -> Self {
+        assert!(
+            piles.len() <= Self::MAX_PILES,
+            "at most {} piles",
+            Self::MAX_PILES
+        );
+        assert!(
+            piles.iter().all(|&pile| pile <= Self::MAX_PILE_SIZE),
+            "piles have at most {} objects",
+            Self::MAX_PILE_SIZE
+        );
+        assert!(number_of_players >= 2, "at least 2 players");
+        NimState {
+            piles,
+            number_of_players,
+            current_player: 0,
+        }
+    }
+
+    /// The player who took the last object if the game is over.
+    fn winner(&self) -> Option<usize> {
+        if self.piles.iter().all(|&pile| pile == 0) {
+            Some((self.current_player + self.number_of_players - 1) % self.number_of_players)
+        } else {
+            None
+        }
+    }
+}
+
+impl GameTreeNode for NimState {
+    type Node = NimState;
+    type Action = NimAction;
+
+    const ACTION_SPACE_SIZE: usize = Self::MAX_PILES * Self::MAX_PILE_SIZE as usize;
+
+    fn current_player(&self) -> usize {
+        self.current_player
+    }
+
+    fn number_of_players(&self) -> usize {
+        self.number_of_players
+    }
+
+    fn calculate_state(&self) -> NodeState<Self::Node> {
+        if let Some(winner) = self.winner() {
+            let mut rewards = vec![-1; self.number_of_players];
+            rewards[winner] = 1;
+            return NodeState::Reward(rewards);
+        }
+
+        NodeState::HasChildren(
+            self.legal_actions()
+                .iter()
+                .map(|action| self.apply(action))
+                .collect(),
+        )
+    }
+
+    fn legal_actions(&self) -> Vec<Self::Action> {
+        self.piles
+            .iter()
+            .enumerate()
+            .flat_map(|(pile, &size)| (1..=size).map(move |count| NimAction { pile, count }))
+            .collect()
+    }
+
+    fn apply(&self, action: &Self::Action) -> Self::Node {
+        assert!(
+            self.piles.get(action.pile) >= Some(&action.count),
+            "cannot play {}, the pile is too small",
+            action
+        );
+        let mut piles = self.piles.clone();
+        piles[action.pile] -= action.count;
+        NimState {
+            piles,
+            number_of_players: self.number_of_players,
+            current_player: (self.current_player + 1) % self.number_of_players,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_player_to_take_wins() {
+        let state = NimState::new(vec![0, 2], 3);
+        let state = state.apply(&"2:1".parse().unwrap());
+        let state = state.apply(&"2:1".parse().unwrap());
+        assert_eq!(state.current_player(), 2);
+        match state.calculate_state() {
+            NodeState::Reward(rewards) => assert_eq!(rewards, vec![-1, 1, -1]),
+            s => panic!("expected NodeState::Reward, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn children_take_from_one_pile() {
+        let state = NimState::new(vec![1, 2], 3);
+        let actions = state.legal_actions();
+        assert_eq!(
+            actions.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            vec!["1:1", "2:1", "2:2"]
+        );
+        for (i, action) in actions.iter().enumerate() {
+            let child = state.apply(action);
+            assert_eq!(child.current_player(), 1);
+            assert_eq!(state.child(i), child);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{GameAction, GameTreeNode, NodeState};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum PigAction {
+    /// Roll the die, adding the roll to the turn total unless it is a 1,
+    /// which loses the turn total and ends the turn.
+    Roll,
+    /// Add the turn total to the player's score and end the turn.
+    Hold,
+}
+
+impl GameAction for PigAction {
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for PigAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PigAction::Roll => write!(f, "roll"),
+            PigAction::Hold => write!(f, "hold"),
+        }
+    }
+}
+
+impl FromStr for PigAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "roll" => Ok(PigAction::Roll),
+            "hold" => Ok(PigAction::Hold),
+            _ => Err(format!("expected 'roll' or 'hold', got '{}'", s)),
+        }
+    }
+}
+
+/// The dice game Pig for two players, the first player to reach the target
+/// score wins with a reward of 1 and the other loses with a reward of -1.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct PigState {
+    scores: [u32; 2],
+    /// The points rolled by the current player this turn, which are lost on a 1.
+    turn_total: u32,
+    target: u32,
+
+    /// The player whose turn it is.
+    current_player: usize,
+    /// Whether the current player has chosen to roll and the die is
+    /// waiting to be rolled, making this a chance node.
+    rolling: bool,
+}
+
+impl PigState {
+    pub fn new(target: u32) -> Self {
+        PigState {
+            scores: [0; 2],
+            turn_total: 0,
+            target,
+            current_player: 0,
+            rolling: false,
+        }
+    }
+
+    fn winner(&self) -> Option<usize> {
+        self.scores.iter().position(|&score| score >= self.target)
+    }
+
+    /// The state after the current player ends their turn, keeping the turn total if `hold`.
+    fn end_turn(&self, hold: bool) -> Self {
+        let mut scores = self.scores;
+        if hold {
+            scores[self.current_player] += self.turn_total;
+        }
+        PigState {
+            scores,
+            turn_total: 0,
+            target: self.target,
+            current_player: 1 - self.current_player,
+            rolling: false,
+        }
+    }
+
+    /// The state after the die lands on `roll`.
+    fn roll(&self, roll: u32) -> Self {
+        if roll == 1 {
+            return self.end_turn(false);
+        }
+
+        let next = PigState {
+            turn_total: self.turn_total + roll,
+            rolling: false,
+            ..self.clone()
+        };
+        // The game ends as soon as the target is reached.
+        if next.scores[next.current_player] + next.turn_total >= next.target {
+            next.end_turn(true)
+        } else {
+            next
+        }
+    }
+}
+
+impl GameTreeNode for PigState {
+    type Node = PigState;
+    type Action = PigAction;
+
+    const ACTION_SPACE_SIZE: usize = 2;
+
+    fn current_player(&self) -> usize {
+        self.current_player
+    }
+
+    fn calculate_state(&self) -> NodeState<Self::Node> {
+        if let Some(winner) = self.winner() {
+            let mut rewards = vec![-1; 2];
+            rewards[winner] = 1;
+            return NodeState::Reward(rewards);
+        }
+
+        if self.rolling {
+            return NodeState::Chance((1..=6).map(|roll| (self.roll(roll), 1.0)).collect());
+        }
+
+        NodeState::HasChildren(
+            self.legal_actions()
+                .iter()
+                .map(|action| self.apply(action))
+                .collect(),
+        )
+    }
+
+    fn legal_actions(&self) -> Vec<Self::Action> {
+        if self.winner().is_some() || self.rolling {
+            return vec![];
+        }
+
+        // Holding without any points would only pass the turn.
+        if self.turn_total == 0 {
+            vec![PigAction::Roll]
+        } else {
+            vec![PigAction::Roll, PigAction::Hold]
+        }
+    }
+
+    fn apply(&self, action: &Self::Action) -> Self::Node {
+        assert!(
+            self.legal_actions().contains(action),
+            "cannot {} from {:?}",
+            action,
+            self
+        );
+        match action {
+            PigAction::Roll => PigState {
+                rolling: true,
+                ..self.clone()
+            },
+            PigAction::Hold => self.end_turn(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_is_a_chance_node() {
+        let state = PigState::new(10).apply(&PigAction::Roll);
+        assert!(state.legal_actions().is_empty());
+
+        let outcomes = match state.calculate_state() {
+            NodeState::Chance(outcomes) => outcomes,
+            s => panic!("expected NodeState::Chance, got {:?}", s),
+        };
+        assert_eq!(outcomes.len(), 6);
+
+        // Rolling a 1 ends the turn, anything else adds to the turn total.
+        assert_eq!(outcomes[0].0.current_player(), 1);
+        assert_eq!(outcomes[0].0.turn_total, 0);
+        assert_eq!(outcomes[3].0.current_player(), 0);
+        assert_eq!(outcomes[3].0.turn_total, 4);
+        assert_eq!(
+            outcomes[3].0.legal_actions(),
+            vec![PigAction::Roll, PigAction::Hold]
+        );
+    }
+
+    #[test]
+    fn reaching_the_target_wins() {
+        let state = PigState {
+            scores: [7, 0],
+            turn_total: 0,
+            target: 10,
+            current_player: 0,
+            rolling: true,
+        };
+
+        match state.roll(3).calculate_state() {
+            NodeState::Reward(rewards) => assert_eq!(rewards, vec![1, -1]),
+            s => panic!("expected NodeState::Reward, got {:?}", s),
+        }
+        assert_eq!(state.roll(2).reward(), None);
+    }
+}
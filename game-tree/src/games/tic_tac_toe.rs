@@ -1,4 +1,6 @@
-use crate::{GameTreeNode, NodeState};
+use crate::{GameAction, GameTreeNode, NodeState};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 enum Player {
@@ -15,6 +17,47 @@ impl Player {
     }
 }
 
+/// Playing in one of the 9 slots, written as the column (a-c) and row (1-3)
+/// of the slot counting from the top left, so "b2" is the center.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct TicTacToeAction {
+    slot: usize,
+}
+
+impl TicTacToeAction {
+    pub fn new(slot: usize) -> Self {
+        assert!(slot < 9, "slot must be less than 9, got {}", slot);
+        TicTacToeAction { slot }
+    }
+}
+
+impl GameAction for TicTacToeAction {
+    fn index(&self) -> usize {
+        self.slot
+    }
+}
+
+impl fmt::Display for TicTacToeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = (b'a' + (self.slot % 3) as u8) as char;
+        write!(f, "{}{}", column, self.slot / 3 + 1)
+    }
+}
+
+impl FromStr for TicTacToeAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        match bytes {
+            [column @ b'a'..=b'c', row @ b'1'..=b'3'] => Ok(TicTacToeAction {
+                slot: usize::from(row - b'1') * 3 + usize::from(column - b'a'),
+            }),
+            _ => Err(format!("expected a column a-c and row 1-3, got '{}'", s)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct TicTacToeState {
     /// The state of a game of tic-tac-toe can be
@@ -25,6 +68,12 @@ pub struct TicTacToeState {
     current_player: Player,
 }
 
+impl Default for TicTacToeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TicTacToeState {
     pub fn new() -> Self {
         TicTacToeState {
@@ -47,6 +96,16 @@ impl TicTacToeState {
         [6, 4, 2],
     ];
 
+    /// The state after the current player plays in the empty `slot`.
+    fn play(&self, slot: usize) -> Self {
+        let mut new_board = self.board;
+        new_board[slot] = Some(self.current_player);
+        TicTacToeState {
+            board: new_board,
+            current_player: self.current_player.next(),
+        }
+    }
+
     fn winner(&self) -> Option<Player> {
         'indices: for indices in &Self::WIN_INDICES {
             let mut winner_for_indices = None;
@@ -77,17 +136,17 @@ impl TicTacToeState {
 
 impl GameTreeNode for TicTacToeState {
     type Node = TicTacToeState;
+    type Action = TicTacToeAction;
+
+    const ACTION_SPACE_SIZE: usize = 9;
 
-    fn is_self_turn(&self) -> bool {
-        self.current_player == Player::X
+    fn current_player(&self) -> usize {
+        self.current_player as usize
     }
 
     fn calculate_state(&self) -> NodeState<Self::Node> {
-        // Return reward if there is a winner.
-        match self.winner() {
-            Some(Player::X) => return NodeState::Reward(1),
-            Some(Player::O) => return NodeState::Reward(-1),
-            _ => (),
+        if let Some(reward) = self.reward() {
+            return NodeState::Reward(reward);
         }
 
         let mut child_nodes = vec![];
@@ -98,20 +157,67 @@ impl GameTreeNode for TicTacToeState {
                 continue;
             }
 
-            let mut new_board = self.board.clone();
-            new_board[i] = Some(self.current_player);
-            child_nodes.push(TicTacToeState {
-                board: new_board,
-                current_player: self.current_player.next(),
-            });
+            child_nodes.push(self.play(i));
+        }
+
+        NodeState::HasChildren(child_nodes)
+    }
+
+    fn legal_actions(&self) -> Vec<Self::Action> {
+        if self.reward().is_some() {
+            return vec![];
+        }
+
+        (0..self.board.len())
+            .filter(|&i| self.board[i].is_none())
+            .map(TicTacToeAction::new)
+            .collect()
+    }
+
+    fn apply(&self, action: &Self::Action) -> Self::Node {
+        assert!(
+            self.board[action.slot].is_none(),
+            "cannot play {}, the slot is occupied",
+            action
+        );
+        self.play(action.slot)
+    }
+
+    fn generates_children_lazily(&self) -> bool {
+        true
+    }
+
+    fn reward(&self) -> Option<Vec<i64>> {
+        // Return reward if there is a winner.
+        match self.winner() {
+            Some(Player::X) => return Some(vec![1, -1]),
+            Some(Player::O) => return Some(vec![-1, 1]),
+            _ => (),
         }
 
         // If no possible moves and no winners, than it is a tie.
-        if child_nodes.is_empty() {
-            NodeState::Reward(0)
+        if self.board.iter().all(|slot| slot.is_some()) {
+            Some(vec![0, 0])
         } else {
-            NodeState::HasChildren(child_nodes)
+            None
+        }
+    }
+
+    fn number_of_children(&self) -> usize {
+        if self.reward().is_some() {
+            return 0;
         }
+
+        // Cannot play any actions if the slot is occupied.
+        self.board.iter().filter(|slot| slot.is_none()).count()
+    }
+
+    fn child(&self, index: usize) -> Self::Node {
+        let slot = (0..self.board.len())
+            .filter(|&i| self.board[i].is_none())
+            .nth(index)
+            .expect("index is less than the number of children");
+        self.play(slot)
     }
 }
 
@@ -134,18 +240,94 @@ mod tests {
         debug_assert_eq!(row_2.len(), 3);
 
         let mut board = [None; 9];
-        board[0] = slot(row_0.chars().nth(0));
+        board[0] = slot(row_0.chars().next());
         board[1] = slot(row_0.chars().nth(1));
         board[2] = slot(row_0.chars().nth(2));
-        board[3] = slot(row_1.chars().nth(0));
+        board[3] = slot(row_1.chars().next());
         board[4] = slot(row_1.chars().nth(1));
         board[5] = slot(row_1.chars().nth(2));
-        board[6] = slot(row_2.chars().nth(0));
+        board[6] = slot(row_2.chars().next());
         board[7] = slot(row_2.chars().nth(1));
         board[8] = slot(row_2.chars().nth(2));
         board
     }
 
+    #[test]
+    fn lazy_children_match_calculate_state() {
+        #[rustfmt::skip]
+        let initial_state = TicTacToeState {
+            board: board(
+                " XX",
+                "OOX",
+                " XO"
+            ),
+            current_player: Player::X,
+        };
+
+        let children = match initial_state.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            s => panic!("expected NodeState::HasChildren, got: {:?}", s),
+        };
+        assert_eq!(initial_state.reward(), None);
+        assert_eq!(initial_state.number_of_children(), children.len());
+        for (i, child) in children.iter().enumerate() {
+            assert_eq!(&initial_state.child(i), child);
+        }
+    }
+
+    #[test]
+    fn legal_actions_match_calculate_state() {
+        #[rustfmt::skip]
+        let initial_state = TicTacToeState {
+            board: board(
+                " XX",
+                "OOX",
+                " XO"
+            ),
+            current_player: Player::X,
+        };
+
+        let children = match initial_state.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            s => panic!("expected NodeState::HasChildren, got: {:?}", s),
+        };
+        let actions = initial_state.legal_actions();
+        assert_eq!(
+            actions.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            vec!["a1", "a3"]
+        );
+        for (action, child) in actions.iter().zip(&children) {
+            assert_eq!(&initial_state.apply(action), child);
+        }
+    }
+
+    #[test]
+    fn action_notation_round_trips() {
+        for slot in 0..TicTacToeState::ACTION_SPACE_SIZE {
+            let action = TicTacToeAction::new(slot);
+            assert_eq!(action.to_string().parse(), Ok(action));
+            assert_eq!(action.index(), slot);
+        }
+        assert_eq!("b2".parse::<TicTacToeAction>().map(|a| a.index()), Ok(4));
+        assert!("d1".parse::<TicTacToeAction>().is_err());
+    }
+
+    #[test]
+    fn children_are_the_legal_actions_applied() {
+        let root = TicTacToeState::new().play(4);
+        let children = match root.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            s => panic!("expected NodeState::HasChildren, got: {:?}", s),
+        };
+
+        let actions = root.legal_actions();
+        assert_eq!(actions.len(), children.len());
+        for (i, (action, child)) in actions.iter().zip(&children).enumerate() {
+            assert_eq!(&root.apply(action), child);
+            assert_eq!(&root.child(i), child);
+        }
+    }
+
     #[test]
     fn state_has_children_works() {
         #[rustfmt::skip]
@@ -206,7 +388,9 @@ mod tests {
         };
 
         match initial_state.calculate_state() {
-            NodeState::Reward(reward) => assert_eq!(reward, 1, "player x gives 1 reward"),
+            NodeState::Reward(reward) => {
+                assert_eq!(reward, vec![1, -1], "player x gives 1 reward")
+            }
             s => panic!("expected NodeState::Reward, got {:?}", s),
         };
     }
@@ -226,7 +410,7 @@ mod tests {
         };
 
         match initial_state.calculate_state() {
-            NodeState::Reward(reward) => assert_eq!(reward, 0, "ties give 0 reward"),
+            NodeState::Reward(reward) => assert_eq!(reward, vec![0, 0], "ties give 0 reward"),
             s => panic!("expected NodeState::Reward, got {:?}", s),
         };
     }
@@ -1,4 +1,4 @@
 pub mod games;
 
 mod game_tree_node;
-pub use self::game_tree_node::{GameTreeNode, NodeState};
+pub use self::game_tree_node::{GameAction, GameTreeNode, NodeState};
@@ -4,19 +4,24 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use criterion::black_box;
 
 use game_tree::games::tic_tac_toe::TicTacToeState;
-use monte_carlo_tree_search::{SearchConfig, SearchTree};
+use monte_carlo_tree_search::{Parallelism, SearchConfig, SearchTree};
 
 pub fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("SearchTree - TicTacToe - 1,000 iters", |b| b.iter(|| {
-        let root = TicTacToeState::new();
-        let tree = SearchTree::new();
-        black_box(tree.search(root, SearchConfig {
-            max_duration: Duration::from_secs(60),
-            max_iterations: 1_000,
+    for &(name, parallelism) in &[("Tree", Parallelism::Tree), ("Root", Parallelism::Root)] {
+        c.bench_function(&format!("SearchTree - TicTacToe - 1,000 iters - {} parallel", name), |b| b.iter(|| {
+            let root = TicTacToeState::new();
+            let tree = SearchTree::new();
+            tree.search(root, SearchConfig {
+                max_duration: Duration::from_secs(60),
+                max_iterations: 1_000,
 
-            exploration_factor: 2.0_f64.sqrt(),
+                exploration_factor: 2.0_f64.sqrt(),
+                parallelism,
+                ..SearchConfig::default()
+            });
+            black_box(tree);
         }));
-    }));
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,20 @@
+use crate::node_metadata::Proof;
+use game_tree::GameTreeNode;
+
+/// The statistics of one move from the root of an analysis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis<N: GameTreeNode> {
+    pub action: N::Action,
+    /// The node the move arrives at.
+    pub node: N,
+    pub number_of_visits: u32,
+    /// The mean reward of the move from the perspective of the player
+    /// making it, 0 if the move was never visited.
+    pub mean_value: f64,
+    /// The prior probability of the move given by the `Evaluator`,
+    /// 0 if the root was never evaluated.
+    pub prior: f64,
+    pub proof: Option<Proof>,
+    /// The most visited line of play expected to follow the move, not including `node`.
+    pub principal_variation: Vec<N>,
+}
@@ -0,0 +1,131 @@
+use game_tree::{GameAction, GameTreeNode, NodeState};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{Rng, RngCore};
+
+/// The estimate an `Evaluator` produces for a leaf of the search tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// The estimated reward of the node for each player, indexed
+    /// by player and on the same scale as `NodeState::Reward`.
+    pub values: Vec<f64>,
+    /// The prior probability of choosing each child, in the
+    /// same order as the children that were evaluated.
+    pub priors: Vec<f64>,
+    /// The actions taken after the evaluated node while simulating the
+    /// game, used by `SearchConfig::rave_equivalence`. Empty if unknown.
+    pub played_actions: Vec<PlayedAction>,
+}
+
+/// An action taken while evaluating a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayedAction {
+    /// The `GameTreeNode::current_player` who took the action.
+    pub player: usize,
+    /// The `GameAction::index` of the action.
+    pub action_index: usize,
+}
+
+impl Evaluation {
+    /// An evaluation where every one of the `number_of_children`
+    /// children is equally likely to be chosen.
+    pub fn with_uniform_priors(values: Vec<f64>, number_of_children: usize) -> Self {
+        let prior = 1.0 / number_of_children as f64;
+        Self {
+            values,
+            priors: vec![prior; number_of_children],
+            played_actions: vec![],
+        }
+    }
+}
+
+/// Estimates the value of leaf nodes reached by the search, replacing
+/// the need to simulate all the way to a terminal node.
+pub trait Evaluator<N: GameTreeNode>: Send + Sync {
+    /// Evaluate a non-terminal `node` whose children are `children`.
+    ///
+    /// Any randomness should come from `rand`, so that seeded searches are reproducible.
+    fn evaluate(&self, node: &N, children: &[N], rand: &mut dyn RngCore) -> Evaluation;
+
+    /// Evaluate several leaves at once, each given as a node with its children.
+    ///
+    /// Evaluators which are more efficient in batches (such as neural networks)
+    /// should override this, by default each leaf is evaluated separately.
+    fn evaluate_batch(&self, leaves: &[(N, Vec<N>)], rand: &mut dyn RngCore) -> Vec<Evaluation> {
+        leaves
+            .iter()
+            .map(|(node, children)| self.evaluate(node, children, rand))
+            .collect()
+    }
+}
+
+/// Evaluates a node by playing uniformly random moves (and sampling the outcomes
+/// of chance nodes) until a reward is reached, all children are given the same prior.
+///
+/// The actions played are reported by their `GameAction::index` if `record_actions` is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomRollout {
+    /// Whether to report the actions played, which lists the legal actions at
+    /// every step. Only needed by `SearchConfig::rave_equivalence`.
+    pub record_actions: bool,
+}
+
+impl<N: GameTreeNode<Node = N>> Evaluator<N> for RandomRollout {
+    fn evaluate(&self, node: &N, children: &[N], rand: &mut dyn RngCore) -> Evaluation {
+        let mut played_actions = vec![];
+        // The child at `index` is reached by the legal action at the same index.
+        let mut record_action = |parent: &N, index: usize| {
+            if !self.record_actions {
+                return;
+            }
+            if let Some(action) = parent.legal_actions().get(index) {
+                played_actions.push(PlayedAction {
+                    player: parent.current_player(),
+                    action_index: action.index(),
+                });
+            }
+        };
+
+        let index = rand.gen_range(0, children.len());
+        let mut current = children[index].clone();
+        record_action(node, index);
+        let rewards = loop {
+            // Only generate the child which is played when possible.
+            if current.generates_children_lazily() {
+                let number_of_children = current.number_of_children();
+                if number_of_children == 0 {
+                    break current.reward().expect("node without children is terminal");
+                }
+
+                let index = rand.gen_range(0, number_of_children);
+                let next = current.child(index);
+                record_action(&current, index);
+                current = next;
+                continue;
+            }
+
+            match current.calculate_state() {
+                NodeState::Reward(rewards) => break rewards,
+                NodeState::Chance(mut outcomes) => {
+                    let index =
+                        WeightedIndex::new(outcomes.iter().map(|(_outcome, weight)| weight))
+                            .expect("outcomes have valid weights")
+                            .sample(rand);
+                    current = outcomes.swap_remove(index).0;
+                }
+                NodeState::HasChildren(mut children) => {
+                    let index = rand.gen_range(0, children.len());
+                    record_action(&current, index);
+                    current = children.swap_remove(index);
+                }
+            }
+        };
+
+        Evaluation {
+            played_actions,
+            ..Evaluation::with_uniform_priors(
+                rewards.into_iter().map(|reward| reward as f64).collect(),
+                children.len(),
+            )
+        }
+    }
+}
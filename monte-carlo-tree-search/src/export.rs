@@ -0,0 +1,259 @@
+use game_tree::{GameTreeNode, NodeState};
+use serde::Serialize;
+use std::io::{self, Write};
+
+use crate::node_metadata::Proof;
+use crate::selection_policy::{SelectionPolicy, Ucb1};
+use crate::SearchTree;
+
+/// Limits on which nodes are included when exporting a `SearchTree`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportLimits {
+    /// The depth of the deepest nodes exported, the root is at depth 0.
+    pub max_depth: usize,
+    /// Nodes with fewer visits are not exported, along with everything below them.
+    pub min_visits: u32,
+}
+
+impl Default for ExportLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            min_visits: 1,
+        }
+    }
+}
+
+/// A node of a `SearchTree` and its statistics, as exported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportedNode {
+    /// The `Debug` representation of the node.
+    pub label: String,
+    pub visits: u32,
+    /// The total reward from the perspective of the player
+    /// who made the move arriving at the node.
+    pub total_reward: f64,
+    pub mean_reward: f64,
+    /// The UCB1 score used when choosing the node from its parent, `None` for
+    /// the root, outcomes of chance nodes and nodes whose score is not finite (such as unvisited nodes).
+    pub uct: Option<f64>,
+    pub proof: Option<Proof>,
+    pub children: Vec<ExportedNode>,
+}
+
+impl SearchTree {
+    /// Walk the nodes below `root` within `limits`, collecting their statistics.
+    /// UCT scores are calculated with `exploration_factor`.
+    pub fn export<N: GameTreeNode<Node = N>>(
+        &self,
+        root: &N,
+        limits: ExportLimits,
+        exploration_factor: f64,
+    ) -> ExportedNode {
+        self.export_node(root, None, 0, limits, exploration_factor)
+    }
+
+    fn export_node<N: GameTreeNode<Node = N>>(
+        &self,
+        node: &N,
+        uct: Option<f64>,
+        depth: usize,
+        limits: ExportLimits,
+        exploration_factor: f64,
+    ) -> ExportedNode {
+        let metadata = self.get_metadata(node);
+        let (visits, total_reward, proof) = metadata
+            .as_ref()
+            .map(|meta| (meta.number_of_visits(), meta.total_reward(), meta.proof()))
+            .unwrap_or((0, 0.0, None));
+        let mean_reward = if visits > 0 {
+            total_reward / f64::from(visits)
+        } else {
+            0.0
+        };
+
+        let mut children = vec![];
+        // The outcomes of chance nodes are sampled rather than selected, so they have no UCT value.
+        let (nodes, is_chance) = match node.calculate_state() {
+            NodeState::HasChildren(children) => (children, false),
+            NodeState::Chance(outcomes) => (
+                outcomes
+                    .into_iter()
+                    .map(|(outcome, _weight)| outcome)
+                    .collect(),
+                true,
+            ),
+            NodeState::Reward(_) => (vec![], false),
+        };
+        if let Some(meta) = &metadata {
+            if depth < limits.max_depth {
+                for child in nodes {
+                    let child_meta = match self.get_metadata(&child) {
+                        Some(child_meta) => child_meta,
+                        None => continue,
+                    };
+                    if child_meta.number_of_visits() < limits.min_visits {
+                        continue;
+                    }
+
+                    let uct = Ucb1.score(
+                        &meta.selection_stats(1.0, None),
+                        &child_meta.selection_stats(child_meta.prior(), None),
+                        exploration_factor,
+                        &mut rand::thread_rng(),
+                    );
+                    let uct = Some(uct).filter(|uct| !is_chance && uct.is_finite());
+                    children.push(self.export_node(
+                        &child,
+                        uct,
+                        depth + 1,
+                        limits,
+                        exploration_factor,
+                    ));
+                }
+            }
+        }
+
+        ExportedNode {
+            label: format!("{:?}", node),
+            visits,
+            total_reward,
+            mean_reward,
+            uct,
+            proof,
+            children,
+        }
+    }
+
+    /// Write the nodes below `root` within `limits` as a Graphviz DOT graph.
+    pub fn write_dot<N: GameTreeNode<Node = N>>(
+        &self,
+        root: &N,
+        limits: ExportLimits,
+        exploration_factor: f64,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let root = self.export(root, limits, exploration_factor);
+
+        writeln!(writer, "digraph SearchTree {{")?;
+        writeln!(writer, "    node [shape=box];")?;
+        let mut next_id = 0;
+        write_dot_node(&root, &mut next_id, &mut writer)?;
+        writeln!(writer, "}}")
+    }
+
+    /// Write the nodes below `root` within `limits` as a JSON document of nested `ExportedNode`s.
+    pub fn write_json<N: GameTreeNode<Node = N>>(
+        &self,
+        root: &N,
+        limits: ExportLimits,
+        exploration_factor: f64,
+        writer: impl Write,
+    ) -> io::Result<()> {
+        let root = self.export(root, limits, exploration_factor);
+        serde_json::to_writer_pretty(writer, &root)?;
+        Ok(())
+    }
+}
+
+/// Write `node` and everything below it, returning the id given to `node`.
+fn write_dot_node(
+    node: &ExportedNode,
+    next_id: &mut usize,
+    writer: &mut impl Write,
+) -> io::Result<usize> {
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut label = format!(
+        "{}\nvisits: {}\ntotal reward: {}\nmean reward: {:.3}",
+        node.label, node.visits, node.total_reward, node.mean_reward
+    );
+    if let Some(uct) = node.uct {
+        label.push_str(&format!("\nuct: {:.3}", uct));
+    }
+    if let Some(proof) = node.proof {
+        label.push_str(&format!("\nproof: {:?}", proof));
+    }
+    writeln!(writer, "    {} [label=\"{}\"];", id, escape(&label))?;
+
+    for child in &node.children {
+        let child_id = write_dot_node(child, next_id, writer)?;
+        writeln!(writer, "    {} -> {};", id, child_id)?;
+    }
+    Ok(id)
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SearchConfig;
+    use game_tree::games::tic_tac_toe::TicTacToeState;
+
+    #[test]
+    fn export_respects_limits() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                max_iterations: 500,
+                seed: Some(0),
+                ..SearchConfig::default()
+            },
+        );
+
+        let exported = tree.export(
+            &root,
+            ExportLimits {
+                max_depth: 1,
+                min_visits: 1,
+            },
+            0.5,
+        );
+        assert!(exported.visits >= 500);
+        assert_eq!(exported.children.len(), 9);
+        assert!(exported
+            .children
+            .iter()
+            .all(|child| child.children.is_empty()));
+        assert!(exported.children.iter().all(|child| child.uct.is_some()));
+
+        let mut dot = vec![];
+        tree.write_dot(
+            &root,
+            ExportLimits {
+                max_depth: 1,
+                min_visits: 1,
+            },
+            0.5,
+            &mut dot,
+        )
+        .unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph SearchTree {"));
+        assert_eq!(dot.matches("->").count(), 9);
+
+        let mut json = vec![];
+        tree.write_json(
+            &root,
+            ExportLimits {
+                max_depth: 1,
+                min_visits: 1,
+            },
+            0.5,
+            &mut json,
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["visits"], exported.visits);
+        assert_eq!(json["children"].as_array().unwrap().len(), 9);
+    }
+}
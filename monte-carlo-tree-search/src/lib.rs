@@ -1,6 +1,29 @@
+mod analysis;
+mod evaluator;
+mod export;
 mod node_metadata;
+mod persistence;
 mod search_config;
+mod search_handle;
 mod search_tree;
+mod selection_policy;
+mod stable_hasher;
+mod temperature;
+mod time_manager;
 
-pub use self::search_config::SearchConfig;
-pub use self::search_tree::SearchTree;
+pub use self::analysis::MoveAnalysis;
+pub use self::evaluator::{Evaluation, Evaluator, PlayedAction, RandomRollout};
+pub use self::export::{ExportLimits, ExportedNode};
+pub use self::node_metadata::Proof;
+pub use self::search_config::{
+    Backup, ChanceBackup, DirichletNoise, EvictionPolicy, NodeBudget, Parallelism,
+    ProgressiveWidening, SearchConfig,
+};
+pub use self::search_handle::SearchHandle;
+pub use self::search_tree::{SearchStats, SearchTree};
+pub use self::selection_policy::{
+    Puct, SelectionPolicy, SelectionStats, Thompson, Ucb1, Ucb1Tuned, UcbV,
+};
+pub use self::stable_hasher::StableHasher;
+pub use self::temperature::TemperatureSchedule;
+pub use self::time_manager::{TimeBudget, TimeControl, TimeManager};
@@ -1,50 +1,376 @@
-use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use crate::persistence::{read_u32, read_u64, read_u8};
+use crate::selection_policy::SelectionStats;
+
+/// A value of a node proven by the search, from the perspective
+/// of the player who made the move arriving at the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Proof {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Proof {
+    pub fn from_reward(reward: f64) -> Self {
+        if reward > 0.0 {
+            Proof::Win
+        } else if reward < 0.0 {
+            Proof::Loss
+        } else {
+            Proof::Draw
+        }
+    }
+
+    /// The reward of a proven node for the player who moved to it,
+    /// assuming that wins are rewarded with 1 and losses with -1.
+    pub fn reward(self) -> f64 {
+        match self {
+            Proof::Win => 1.0,
+            Proof::Draw => 0.0,
+            Proof::Loss => -1.0,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Proof::Win => Proof::Loss,
+            Proof::Draw => Proof::Draw,
+            Proof::Loss => Proof::Win,
+        }
+    }
+
+    /// The proof for the player choosing between `children`: a win if any child
+    /// is a win, otherwise the best result once every child is proven.
+    pub fn for_chooser_of(children: &[Arc<NodeMetadata>]) -> Option<Self> {
+        if children.is_empty() {
+            return None;
+        }
+
+        let proofs = children
+            .iter()
+            .map(|child| child.proof())
+            .collect::<Vec<_>>();
+        if proofs.contains(&Some(Proof::Win)) {
+            Some(Proof::Win)
+        } else if proofs.contains(&None) {
+            None
+        } else if proofs.contains(&Some(Proof::Draw)) {
+            Some(Proof::Draw)
+        } else {
+            Some(Proof::Loss)
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Proof::Win => 1,
+            Proof::Draw => 2,
+            Proof::Loss => 3,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Proof::Win),
+            2 => Some(Proof::Draw),
+            3 => Some(Proof::Loss),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct NodeMetadata {
-    total_reward: AtomicI64,
+    /// Sum of all rewards recorded, stored as the bits of an f64.
+    total_reward: AtomicU64,
+    /// Sum of the squares of all rewards recorded, stored as the bits of an f64.
+    total_squared_reward: AtomicU64,
     number_of_visits: AtomicU32,
 
+    /// Visits and (negative) reward of descents which have not been
+    /// backed up yet, the reward is stored as the bits of an f64.
+    virtual_visits: AtomicU32,
+    virtual_reward: AtomicU64,
+
+    /// Visits and total reward of simulations where the action arriving at this
+    /// node was taken later on by the same player (all-moves-as-first).
+    amaf_visits: AtomicU32,
+    amaf_reward: AtomicU64,
+
+    /// Prior probability of choosing this node from its parent,
+    /// stored as the bits of an f64.
+    prior: AtomicU64,
+
+    /// An independent hash of the node, used to detect collisions.
+    check_hash: Option<u64>,
+
+    /// The `Proof` of the node, 0 when unproven.
+    proof: AtomicU8,
+
+    /// The generation of the last search which used this node.
+    generation: AtomicU32,
+
     visited: AtomicBool,
     fully_expanded: AtomicBool,
+    children_priors_set: AtomicBool,
+
+    /// The hash and check hash of each child of a node which generates its children
+    /// lazily, stored once it is descended through so later descents only have to
+    /// generate the child they choose.
+    children_hashes: OnceLock<Box<[(u64, u64)]>>,
 }
 
 impl NodeMetadata {
-    pub fn new() -> Self {
+    pub fn new(check_hash: Option<u64>) -> Self {
         Self {
-            total_reward: AtomicI64::new(0),
+            total_reward: AtomicU64::new(0.0_f64.to_bits()),
+            total_squared_reward: AtomicU64::new(0.0_f64.to_bits()),
             number_of_visits: AtomicU32::new(0),
 
+            virtual_visits: AtomicU32::new(0),
+            virtual_reward: AtomicU64::new(0.0_f64.to_bits()),
+
+            amaf_visits: AtomicU32::new(0),
+            amaf_reward: AtomicU64::new(0.0_f64.to_bits()),
+
+            prior: AtomicU64::new(0.0_f64.to_bits()),
+
+            check_hash,
+
+            proof: AtomicU8::new(0),
+
+            generation: AtomicU32::new(0),
+
             visited: AtomicBool::new(false),
             fully_expanded: AtomicBool::new(false),
+            children_priors_set: AtomicBool::new(false),
+
+            children_hashes: OnceLock::new(),
         }
     }
 
-    pub fn record_result(&self, reward: i64) {
+    /// Write the persistent statistics of the node, everything except
+    /// the virtual loss of in-flight descents and the generation.
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self.check_hash {
+            Some(check_hash) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&check_hash.to_le_bytes())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.write_all(&self.number_of_visits().to_le_bytes())?;
+        writer.write_all(&self.total_reward.load(Ordering::SeqCst).to_le_bytes())?;
+        writer.write_all(
+            &self
+                .total_squared_reward
+                .load(Ordering::SeqCst)
+                .to_le_bytes(),
+        )?;
+        writer.write_all(&self.amaf_visits.load(Ordering::SeqCst).to_le_bytes())?;
+        writer.write_all(&self.amaf_reward.load(Ordering::SeqCst).to_le_bytes())?;
+        writer.write_all(&self.prior.load(Ordering::SeqCst).to_le_bytes())?;
+        writer.write_all(&[self.proof.load(Ordering::SeqCst)])?;
+
+        let flags = (self.is_visited() as u8)
+            | (self.is_fully_expanded() as u8) << 1
+            | (self.are_children_priors_set() as u8) << 2;
+        writer.write_all(&[flags])
+    }
+
+    /// Read statistics written by `write_to`.
+    pub(crate) fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let check_hash = match read_u8(reader)? {
+            0 => None,
+            _ => Some(read_u64(reader)?),
+        };
+        let metadata = Self::new(check_hash);
+        metadata
+            .number_of_visits
+            .store(read_u32(reader)?, Ordering::SeqCst);
+        metadata
+            .total_reward
+            .store(read_u64(reader)?, Ordering::SeqCst);
+        metadata
+            .total_squared_reward
+            .store(read_u64(reader)?, Ordering::SeqCst);
+        metadata
+            .amaf_visits
+            .store(read_u32(reader)?, Ordering::SeqCst);
+        metadata
+            .amaf_reward
+            .store(read_u64(reader)?, Ordering::SeqCst);
+        metadata.prior.store(read_u64(reader)?, Ordering::SeqCst);
+
+        let proof = read_u8(reader)?;
+        if proof != 0 && Proof::from_u8(proof).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid proof"));
+        }
+        metadata.proof.store(proof, Ordering::SeqCst);
+
+        let flags = read_u8(reader)?;
+        metadata.visited.store(flags & 1 != 0, Ordering::SeqCst);
+        metadata
+            .fully_expanded
+            .store(flags & 2 != 0, Ordering::SeqCst);
+        metadata
+            .children_priors_set
+            .store(flags & 4 != 0, Ordering::SeqCst);
+        Ok(metadata)
+    }
+
+    pub fn record_result(&self, reward: f64) {
         self.number_of_visits.fetch_add(1, Ordering::SeqCst);
-        self.total_reward.fetch_add(reward, Ordering::SeqCst);
+        fetch_add_f64(&self.total_reward, reward);
+        fetch_add_f64(&self.total_squared_reward, reward * reward);
 
         self.visited.store(true, Ordering::SeqCst);
     }
 
+    /// Add the statistics of `other`, the same node searched in a different tree.
+    ///
+    /// Whether the node was visited, expanded or had its children's priors set is
+    /// not merged, since the children of `other` aren't merged along with it.
+    pub fn merge(&self, other: &NodeMetadata) {
+        self.number_of_visits
+            .fetch_add(other.number_of_visits(), Ordering::SeqCst);
+        fetch_add_f64(&self.total_reward, other.total_reward());
+        fetch_add_f64(
+            &self.total_squared_reward,
+            f64::from_bits(other.total_squared_reward.load(Ordering::SeqCst)),
+        );
+        self.amaf_visits
+            .fetch_add(other.amaf_visits(), Ordering::SeqCst);
+        fetch_add_f64(
+            &self.amaf_reward,
+            f64::from_bits(other.amaf_reward.load(Ordering::SeqCst)),
+        );
+
+        if self.prior() == 0.0 {
+            self.set_prior(other.prior());
+        }
+        if let Some(proof) = other.proof() {
+            self.set_proof(proof);
+        }
+    }
+
     pub fn number_of_visits(&self) -> u32 {
         self.number_of_visits.load(Ordering::SeqCst)
     }
 
-    pub fn uct(&self, parent_metadata: &NodeMetadata, exploration_factor: f64) -> f64 {
+    pub fn total_reward(&self) -> f64 {
+        f64::from_bits(self.total_reward.load(Ordering::SeqCst))
+    }
+
+    pub fn record_amaf_result(&self, reward: f64) {
+        self.amaf_visits.fetch_add(1, Ordering::SeqCst);
+        fetch_add_f64(&self.amaf_reward, reward);
+    }
+
+    pub fn amaf_visits(&self) -> u32 {
+        self.amaf_visits.load(Ordering::SeqCst)
+    }
+
+    /// The mean reward, blended with the all-moves-as-first mean reward when
+    /// `rave_equivalence` is provided. `None` if there are no statistics at all.
+    fn exploitation(&self, rave_equivalence: Option<f64>) -> Option<f64> {
+        let number_of_visits = self.effective_number_of_visits();
+        let mean_reward = if number_of_visits == 0.0 {
+            None
+        } else {
+            Some(self.effective_total_reward() / number_of_visits)
+        };
+
+        let amaf_visits = f64::from(self.amaf_visits());
+        match (rave_equivalence, mean_reward) {
+            (Some(equivalence), _) if amaf_visits > 0.0 => {
+                let amaf_mean_reward =
+                    f64::from_bits(self.amaf_reward.load(Ordering::SeqCst)) / amaf_visits;
+                let beta = (equivalence / (3.0 * number_of_visits + equivalence)).sqrt();
+                Some((1.0 - beta) * mean_reward.unwrap_or(0.0) + beta * amaf_mean_reward)
+            }
+            _ => mean_reward,
+        }
+    }
+
+    pub fn add_virtual_loss(&self, loss: f64) {
+        self.virtual_visits.fetch_add(1, Ordering::SeqCst);
+        fetch_add_f64(&self.virtual_reward, -loss);
+    }
+
+    pub fn remove_virtual_loss(&self, loss: f64) {
+        self.virtual_visits.fetch_sub(1, Ordering::SeqCst);
+        fetch_add_f64(&self.virtual_reward, loss);
+    }
+
+    pub fn has_virtual_loss(&self) -> bool {
+        self.virtual_visits.load(Ordering::SeqCst) > 0
+    }
+
+    /// Number of visits including the in-flight descents.
+    fn effective_number_of_visits(&self) -> f64 {
+        (self.number_of_visits() + self.virtual_visits.load(Ordering::SeqCst)) as f64
+    }
+
+    /// Total reward including the virtual loss of in-flight descents.
+    fn effective_total_reward(&self) -> f64 {
+        self.total_reward() + f64::from_bits(self.virtual_reward.load(Ordering::SeqCst))
+    }
+
+    pub fn prior(&self) -> f64 {
+        f64::from_bits(self.prior.load(Ordering::SeqCst))
+    }
+
+    pub fn set_prior(&self, prior: f64) {
+        self.prior.store(prior.to_bits(), Ordering::SeqCst);
+    }
+
+    /// The statistics used by a `SelectionPolicy`, the mean reward includes
+    /// RAVE statistics when `rave_equivalence` is provided.
+    pub fn selection_stats(&self, prior: f64, rave_equivalence: Option<f64>) -> SelectionStats {
         // Potentially inaccurate due to the non-atomic loading of all of these values
         // But IMO not harmful to the guarantees of the search.
-        let parent_number_of_visits = parent_metadata.number_of_visits() as f64;
-        let number_of_visits = self.number_of_visits() as f64;
-        let total_reward = self.total_reward.load(Ordering::SeqCst) as f64;
+        let number_of_visits = f64::from(self.number_of_visits());
+        let reward_variance = if number_of_visits > 0.0 {
+            let mean_reward = self.total_reward() / number_of_visits;
+            let mean_squared_reward =
+                f64::from_bits(self.total_squared_reward.load(Ordering::SeqCst)) / number_of_visits;
+            (mean_squared_reward - mean_reward * mean_reward).max(0.0)
+        } else {
+            0.0
+        };
+
+        SelectionStats {
+            number_of_visits: self.effective_number_of_visits(),
+            mean_reward: self.exploitation(rave_equivalence),
+            reward_variance,
+            prior,
+        }
+    }
+
+    pub fn check_hash(&self) -> Option<u64> {
+        self.check_hash
+    }
+
+    pub fn proof(&self) -> Option<Proof> {
+        Proof::from_u8(self.proof.load(Ordering::SeqCst))
+    }
 
-        debug_assert!(number_of_visits != 0.0);
+    pub fn set_proof(&self, proof: Proof) {
+        self.proof.store(proof.to_u8(), Ordering::SeqCst);
+    }
 
-        let exploitation_component = total_reward / number_of_visits;
-        let exploration_component =
-            exploration_factor * (parent_number_of_visits.log2() / number_of_visits).sqrt();
+    pub fn generation(&self) -> u32 {
+        self.generation.load(Ordering::SeqCst)
+    }
 
-        exploitation_component + exploration_component
+    pub fn set_generation(&self, generation: u32) {
+        self.generation.store(generation, Ordering::SeqCst);
     }
 
     pub fn is_visited(&self) -> bool {
@@ -58,4 +384,83 @@ impl NodeMetadata {
     pub fn is_fully_expanded(&self) -> bool {
         self.fully_expanded.load(Ordering::SeqCst)
     }
+
+    /// Marks that the priors of the children of this node have been assigned.
+    pub fn set_children_priors_set(&self) {
+        self.children_priors_set.store(true, Ordering::SeqCst);
+    }
+
+    pub fn are_children_priors_set(&self) -> bool {
+        self.children_priors_set.load(Ordering::SeqCst)
+    }
+
+    pub fn children_hashes(&self) -> Option<&[(u64, u64)]> {
+        self.children_hashes.get().map(|hashes| &hashes[..])
+    }
+
+    /// Store the hash and check hash of each child, unless they're already stored.
+    pub fn set_children_hashes(&self, hashes: Vec<(u64, u64)>) {
+        let _ = self.children_hashes.set(hashes.into_boxed_slice());
+    }
+}
+
+fn fetch_add_f64(atomic: &AtomicU64, value: f64) {
+    let mut current = atomic.load(Ordering::SeqCst);
+    loop {
+        let new = (f64::from_bits(current) + value).to_bits();
+        match atomic.compare_exchange_weak(current, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return,
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rave_blends_amaf_statistics() {
+        let node = NodeMetadata::new(None);
+        node.record_result(-1.0);
+        node.record_amaf_result(1.0);
+
+        assert_eq!(node.selection_stats(1.0, None).mean_reward, Some(-1.0));
+        // Equal weight given to both with an equivalence parameter of 1 and a single visit.
+        assert_eq!(node.selection_stats(1.0, Some(1.0)).mean_reward, Some(0.0));
+        // Unvisited nodes are valued by their all-moves-as-first statistics alone.
+        let unvisited = NodeMetadata::new(None);
+        unvisited.record_amaf_result(1.0);
+        assert_eq!(
+            unvisited.selection_stats(1.0, Some(1.0)).mean_reward,
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn reward_variance_is_tracked() {
+        let node = NodeMetadata::new(None);
+        node.record_result(1.0);
+        node.record_result(-1.0);
+
+        let stats = node.selection_stats(1.0, None);
+        assert_eq!(stats.mean_reward, Some(0.0));
+        assert!((stats.reward_variance - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn virtual_loss_discourages_selection_until_removed() {
+        let node = NodeMetadata::new(None);
+        node.record_result(1.0);
+        let stats = node.selection_stats(1.0, None);
+
+        node.add_virtual_loss(1.0);
+        let with_virtual_loss = node.selection_stats(1.0, None);
+        assert!(with_virtual_loss.mean_reward < stats.mean_reward);
+        assert_eq!(with_virtual_loss.number_of_visits, 2.0);
+
+        node.remove_virtual_loss(1.0);
+        assert!(!node.has_virtual_loss());
+        assert_eq!(node.selection_stats(1.0, None), stats);
+    }
 }
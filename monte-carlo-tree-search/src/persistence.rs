@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::node_metadata::NodeMetadata;
+use crate::search_tree::{CHECK_HASH_SEED, HASH_SEED};
+use crate::SearchTree;
+
+const MAGIC: &[u8; 4] = b"MCTS";
+/// Incremented whenever the layout of the saved statistics changes.
+const FORMAT_VERSION: u32 = 3;
+/// Identifies the algorithm used to hash nodes, saved statistics are
+/// only meaningful if nodes are hashed the same way when they are loaded.
+const HASHING_SCHEME: &str = "fnv1a-splitmix64";
+/// The longest string saved (the hashing scheme and game), so that a corrupted
+/// length can't allocate an arbitrary amount of memory when loading.
+const MAX_STR_LEN: usize = 1024;
+
+impl SearchTree {
+    /// Write the statistics of every node to `writer` in a versioned binary format.
+    ///
+    /// `game` identifies the game the tree was searched on, so that
+    /// statistics are not accidentally loaded for a different game,
+    /// it can be at most 1024 bytes long.
+    pub fn save(&self, game: &str, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        write_str(&mut writer, HASHING_SCHEME)?;
+        writer.write_all(&HASH_SEED.to_le_bytes())?;
+        writer.write_all(&CHECK_HASH_SEED.to_le_bytes())?;
+        write_str(&mut writer, game)?;
+
+        // Collected first since nodes could be added while writing.
+        let nodes = self
+            .node_metadata
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        writer.write_all(&(nodes.len() as u64).to_le_bytes())?;
+        for (node_hash, metadata) in nodes {
+            writer.write_all(&node_hash.to_le_bytes())?;
+            metadata.write_to(&mut writer)?;
+        }
+
+        let collisions = self
+            .colliding_node_metadata
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        writer.write_all(&(collisions.len() as u64).to_le_bytes())?;
+        for ((node_hash, check_hash), metadata) in collisions {
+            writer.write_all(&node_hash.to_le_bytes())?;
+            writer.write_all(&check_hash.to_le_bytes())?;
+            metadata.write_to(&mut writer)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Read a tree written by `save`, failing with `io::ErrorKind::InvalidData`
+    /// if it was saved for a different `game`, format version or hashing scheme.
+    pub fn load(game: &str, mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a saved search tree".to_string()));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported format version {}, expected {}",
+                version, FORMAT_VERSION
+            )));
+        }
+
+        let hashing_scheme = read_str(&mut reader)?;
+        let hash_seed = read_u64(&mut reader)?;
+        let check_hash_seed = read_u64(&mut reader)?;
+        if hashing_scheme != HASHING_SCHEME
+            || hash_seed != HASH_SEED
+            || check_hash_seed != CHECK_HASH_SEED
+        {
+            return Err(invalid_data(format!(
+                "saved with hashing scheme {} (seeds {:#x}, {:#x}), which is not in use",
+                hashing_scheme, hash_seed, check_hash_seed
+            )));
+        }
+
+        let saved_game = read_str(&mut reader)?;
+        if saved_game != game {
+            return Err(invalid_data(format!(
+                "saved for game {}, not {}",
+                saved_game, game
+            )));
+        }
+
+        let tree = SearchTree::new();
+        let number_of_nodes = read_u64(&mut reader)?;
+        for _ in 0..number_of_nodes {
+            let node_hash = read_u64(&mut reader)?;
+            let metadata = NodeMetadata::read_from(&mut reader)?;
+            tree.node_metadata.insert(node_hash, Arc::new(metadata));
+        }
+
+        let number_of_collisions = read_u64(&mut reader)?;
+        for _ in 0..number_of_collisions {
+            let node_hash = read_u64(&mut reader)?;
+            let check_hash = read_u64(&mut reader)?;
+            let metadata = NodeMetadata::read_from(&mut reader)?;
+            tree.colliding_node_metadata
+                .insert((node_hash, check_hash), Arc::new(metadata));
+        }
+        tree.number_of_nodes.store(
+            (number_of_nodes + number_of_collisions) as usize,
+            Ordering::SeqCst,
+        );
+        tree.number_of_collisions
+            .store(number_of_collisions, Ordering::SeqCst);
+
+        Ok(tree)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_str(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    if value.len() > MAX_STR_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "strings are at most {} bytes, got {}",
+                MAX_STR_LEN,
+                value.len()
+            ),
+        ));
+    }
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_str(reader: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(reader)? as usize;
+    if len > MAX_STR_LEN {
+        return Err(invalid_data(format!(
+            "strings are at most {} bytes, got {}",
+            MAX_STR_LEN, len
+        )));
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("string is not utf-8".to_string()))
+}
+
+pub(crate) fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SearchConfig;
+    use game_tree::games::tic_tac_toe::TicTacToeState;
+    use game_tree::{GameTreeNode, NodeState};
+
+    #[test]
+    fn saved_tree_loads_with_the_same_statistics() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                max_iterations: 200,
+                seed: Some(0),
+                ..SearchConfig::default()
+            },
+        );
+
+        let mut bytes = vec![];
+        tree.save("TicTacToe", &mut bytes).unwrap();
+        let loaded = SearchTree::load("TicTacToe", &bytes[..]).unwrap();
+
+        assert_eq!(loaded.number_of_nodes(), tree.number_of_nodes());
+        assert_eq!(
+            loaded.get_number_of_visits(&root),
+            tree.get_number_of_visits(&root)
+        );
+        if let NodeState::HasChildren(children) = root.calculate_state() {
+            for child in children {
+                assert_eq!(
+                    loaded.get_number_of_visits(&child),
+                    tree.get_number_of_visits(&child)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn load_rejects_a_different_game() {
+        let mut bytes = vec![];
+        SearchTree::new().save("TicTacToe", &mut bytes).unwrap();
+
+        let error = SearchTree::load("Nim", &bytes[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_rejects_a_corrupted_string_length() {
+        let mut bytes = vec![];
+        SearchTree::new().save("TicTacToe", &mut bytes).unwrap();
+        // The length of the hashing scheme follows the magic and format version.
+        bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let error = SearchTree::load("TicTacToe", &bytes[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
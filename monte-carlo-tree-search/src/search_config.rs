@@ -1,11 +1,211 @@
+use rand::Rng;
+use rand_distr::{Distribution, Gamma};
+use std::mem;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::node_metadata::NodeMetadata;
+use crate::selection_policy::{SelectionPolicy, Ucb1};
+
 #[derive(Debug, Clone)]
 pub struct SearchConfig {
     pub max_duration: Duration,
     pub max_iterations: u64,
 
+    /// Scales the exploration component of the selection formula,
+    /// used as `c_puct` when selecting with `Puct`.
     pub exploration_factor: f64,
+    /// Chooses which child to descend into, `Ucb1` by default.
+    pub selection: Arc<dyn SelectionPolicy>,
+    /// Noise mixed into the priors of the children of the root to diversify
+    /// self-play, only used by selection policies which use priors (such as `Puct`).
+    pub root_noise: Option<DirichletNoise>,
+    /// Blend all-moves-as-first statistics into the value of nodes during selection
+    /// (RAVE), weighted by `sqrt(k / (3 * N + k))` where `k` is the equivalence
+    /// parameter and `N` the visits of the node. Actions taken from different
+    /// nodes are matched by their `GameAction::index`. `None` to disable.
+    pub rave_equivalence: Option<f64>,
+    /// Only consider a number of children which grows with the visits of a node,
+    /// so that the search goes deeper in games with many moves. `None` to
+    /// consider every child.
+    pub progressive_widening: Option<ProgressiveWidening>,
+    /// How the rewards of each player are backed up in games with more than
+    /// two players, in two-player games every backup is the same.
+    pub backup: Backup,
+    /// How the value of chance nodes is backed up, outcomes
+    /// are always sampled by their weight while descending.
+    pub chance_backup: ChanceBackup,
+
+    /// The loss temporarily recorded on every node of an in-flight descent,
+    /// discouraging other descents from choosing the same path.
+    pub virtual_loss: f64,
+    /// The number of leaves each search task collects before
+    /// evaluating them together with `Evaluator::evaluate_batch`.
+    pub batch_size: usize,
+
+    /// The maximum size of the tree, nodes are evicted with
+    /// `eviction_policy` once it is reached. `None` for no limit.
+    pub node_budget: Option<NodeBudget>,
+    pub eviction_policy: EvictionPolicy,
+
+    /// Store a second, independent hash with each node so that different
+    /// nodes which share a hash are detected and given separate statistics.
+    pub verify_hashes: bool,
+
+    /// How the search is split between threads.
+    pub parallelism: Parallelism,
+    /// The number of threads used by `Parallelism::Tree` and `Parallelism::Root`,
+    /// which search on a thread pool of this size (kept by the `SearchTree` for later
+    /// searches), `None` to search on rayon's global thread pool (one thread per CPU
+    /// unless configured otherwise).
+    pub number_of_threads: Option<usize>,
+
+    /// Search deterministically on a single thread (regardless of `parallelism`)
+    /// with random choices seeded by `seed`, the same tree, root, config and seed
+    /// give identical statistics as long as `max_duration` is not reached.
+    pub seed: Option<u64>,
+
+    /// Prove wins, losses and draws (MCTS-Solver) so that selection always
+    /// chooses proven wins and avoids proven losses. A positive reward is
+    /// treated as a win and proven nodes are backed up with a reward of
+    /// 1, 0 or -1, so this is only meaningful for two-player win/draw/loss games
+    /// and is ignored in games with more players.
+    pub solver: bool,
+
+    /// End the search once the most visited child of the root can't be overtaken
+    /// in the iterations remaining of `max_iterations`, since the move chosen
+    /// by `SearchTree::select_most_visited_child` would be the same.
+    ///
+    /// Ignored by unseeded `Parallelism::Root` searches, since each thread only
+    /// knows the visits of its private tree until the search ends.
+    pub smart_stop: bool,
+}
+
+/// How a search is split between threads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parallelism {
+    /// Search on the calling thread only, useful when
+    /// many searches are already running in parallel.
+    SingleThreaded,
+    /// Threads search the same tree at the same time, sharing statistics
+    /// as they go. Virtual loss keeps the threads on different paths.
+    Tree,
+    /// Each thread searches its own private tree, which avoids contention between
+    /// threads. Once the search ends the statistics of the root and its children
+    /// are added to the shared tree, the rest of the private trees are discarded.
+    Root,
+}
+
+/// How the rewards of each player are backed up through the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backup {
+    /// Every node records the reward of the player who moved to it (max^n),
+    /// so each player is assumed to maximize their own reward.
+    MaxN,
+    /// The other players are assumed to form a coalition minimizing the reward of
+    /// the player to move at the root, so the nodes they move to record its negation.
+    /// Statistics are only meaningful when searching from the same player's turn.
+    Paranoid,
+}
+
+/// How the value of a `NodeState::Chance` node is backed up through the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChanceBackup {
+    /// Record the reward of the sampled outcome, so the value of the chance
+    /// node converges to the expectation as outcomes are sampled.
+    Sampled,
+    /// Record the weighted average of the mean rewards of the outcomes visited
+    /// so far, which reduces the variance of the sampled rewards.
+    Expectation,
+}
+
+/// Progressive widening, only the `ceil(k * N^alpha)` children with the highest
+/// priors are considered when descending through a node with `N` visits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressiveWidening {
+    pub k: f64,
+    pub alpha: f64,
+}
+
+impl ProgressiveWidening {
+    /// The number of children considered for a node with `number_of_visits`, at least 1.
+    pub fn number_of_children(self, number_of_visits: u32) -> usize {
+        let number_of_children = (self.k * f64::from(number_of_visits).powf(self.alpha)).ceil();
+        (number_of_children as usize).max(1)
+    }
+}
+
+/// AlphaZero-style noise, the priors of the children of the root
+/// become `(1 - epsilon) * P + epsilon * Dir(alpha)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirichletNoise {
+    pub(crate) alpha: f64,
+    pub(crate) epsilon: f64,
+}
+
+impl DirichletNoise {
+    /// Panics unless `alpha` is positive and `epsilon` is between 0 and 1.
+    pub fn new(alpha: f64, epsilon: f64) -> Self {
+        assert!(alpha > 0.0, "alpha must be positive, got {}", alpha);
+        assert!(
+            (0.0..=1.0).contains(&epsilon),
+            "epsilon must be between 0 and 1, got {}",
+            epsilon
+        );
+        DirichletNoise { alpha, epsilon }
+    }
+
+    /// Sample noise for `number_of_children` children, which sums to 1.
+    pub(crate) fn sample(self, number_of_children: usize, rand: &mut impl Rng) -> Vec<f64> {
+        // Normalized samples of Gamma(alpha, 1) are distributed as Dir(alpha).
+        let gamma = Gamma::new(self.alpha, 1.0).expect("alpha is positive");
+        let samples = (0..number_of_children)
+            .map(|_| gamma.sample(rand))
+            .collect::<Vec<f64>>();
+
+        let total = samples.iter().sum::<f64>();
+        if total > 0.0 {
+            samples.into_iter().map(|sample| sample / total).collect()
+        } else {
+            vec![1.0 / number_of_children as f64; number_of_children]
+        }
+    }
+}
+
+/// A limit on the size of a `SearchTree`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeBudget {
+    /// Maximum number of nodes stored.
+    Count(usize),
+    /// Approximate maximum number of bytes used by the stored nodes.
+    Bytes(usize),
+}
+
+impl NodeBudget {
+    pub fn max_nodes(self) -> usize {
+        match self {
+            NodeBudget::Count(count) => count,
+            NodeBudget::Bytes(bytes) => {
+                // Key and value in the map, plus the reference counts of the Arc. The
+                // children hashes stored by lazily generated nodes aren't counted.
+                let bytes_per_node = mem::size_of::<u64>()
+                    + mem::size_of::<Arc<NodeMetadata>>()
+                    + 2 * mem::size_of::<usize>()
+                    + mem::size_of::<NodeMetadata>();
+                bytes / bytes_per_node
+            }
+        }
+    }
+}
+
+/// Which nodes are evicted first once the `NodeBudget` is reached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    /// Evict the nodes with the fewest visits.
+    LeastVisited,
+    /// Evict the nodes which were last used by the oldest search,
+    /// the fewest visits are evicted first within a search.
+    OldestGeneration,
 }
 
 impl Default for SearchConfig {
@@ -15,6 +215,60 @@ impl Default for SearchConfig {
             max_iterations: 1_000,
 
             exploration_factor: 0.5,
+            selection: Arc::new(Ucb1),
+            root_noise: None,
+            rave_equivalence: None,
+            progressive_widening: None,
+            backup: Backup::MaxN,
+            chance_backup: ChanceBackup::Sampled,
+
+            virtual_loss: 1.0,
+            batch_size: 1,
+
+            node_budget: None,
+            eviction_policy: EvictionPolicy::LeastVisited,
+
+            verify_hashes: false,
+
+            parallelism: Parallelism::Tree,
+            number_of_threads: None,
+
+            seed: None,
+
+            solver: false,
+
+            smart_stop: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn progressive_widening_grows_with_visits() {
+        let widening = ProgressiveWidening { k: 2.0, alpha: 0.5 };
+        assert_eq!(widening.number_of_children(0), 1);
+        assert_eq!(widening.number_of_children(1), 2);
+        assert_eq!(widening.number_of_children(100), 20);
+    }
+
+    #[test]
+    fn dirichlet_noise_is_a_distribution() {
+        let noise = DirichletNoise::new(0.3, 0.25);
+        let samples = noise.sample(9, &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(samples.len(), 9);
+        assert!(samples.iter().all(|&sample| sample >= 0.0));
+        assert!((samples.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must be positive")]
+    fn dirichlet_noise_requires_positive_alpha() {
+        DirichletNoise::new(0.0, 0.25);
+    }
+}
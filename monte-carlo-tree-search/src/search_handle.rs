@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A search running on a background thread, started with `SearchTree::start_search`.
+///
+/// The search is stopped when the handle is dropped.
+pub struct SearchHandle {
+    number_iterations: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+    start: Instant,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SearchHandle {
+    /// Run `search` on a new thread, passing the iteration counter and stop flag.
+    pub(crate) fn spawn(
+        search: impl FnOnce(Arc<AtomicU64>, Arc<AtomicBool>) + Send + 'static,
+    ) -> Self {
+        let number_iterations = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let number_iterations = Arc::clone(&number_iterations);
+            let stop = Arc::clone(&stop);
+            let finished = Arc::clone(&finished);
+            thread::spawn(move || {
+                search(number_iterations, stop);
+                finished.store(true, Ordering::SeqCst);
+            })
+        };
+
+        Self {
+            number_iterations,
+            stop,
+            finished,
+            start: Instant::now(),
+            thread: Some(thread),
+        }
+    }
+
+    /// The number of iterations completed so far.
+    pub fn number_of_iterations(&self) -> u64 {
+        self.number_iterations.load(Ordering::SeqCst)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Whether the search has finished, either by reaching
+    /// the limits of its config or by being stopped.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Stop the search and wait for the iterations in progress to be backed up.
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.join();
+    }
+
+    /// Wait for the search to reach the limits of its config.
+    pub fn wait(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            if let Err(panic) = thread.join() {
+                // Propagate panics from the search, unless already panicking.
+                if !thread::panicking() {
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.join();
+    }
+}
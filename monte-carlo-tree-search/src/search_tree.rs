@@ -1,48 +1,251 @@
 use ccl::dhashmap::DHashMap;
-use game_tree::{GameTreeNode, NodeState};
+use game_tree::{GameAction, GameTreeNode, NodeState};
 use ordered_float::OrderedFloat;
-use rand::seq::{IteratorRandom, SliceRandom};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-use std::collections::hash_map::DefaultHasher;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::hash::Hasher;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::node_metadata::NodeMetadata;
+use crate::analysis::MoveAnalysis;
+use crate::evaluator::{Evaluator, PlayedAction, RandomRollout};
+use crate::node_metadata::{NodeMetadata, Proof};
+use crate::search_config::{Backup, ChanceBackup, EvictionPolicy, Parallelism};
+use crate::search_handle::SearchHandle;
+use crate::stable_hasher::StableHasher;
+use crate::temperature::apply_temperature;
 use crate::SearchConfig;
 
 type MetadataMap = DHashMap<u64, Arc<NodeMetadata>>;
+/// Nodes whose hash collided with a different node, keyed by both hashes.
+type CollisionMap = DHashMap<(u64, u64), Arc<NodeMetadata>>;
+
+/// Seeds of the hashes, different so the check hash is independent of the hash used as the key.
+pub(crate) const HASH_SEED: u64 = 0;
+pub(crate) const CHECK_HASH_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The fraction of the node budget which is evicted at once, so
+/// that eviction does not need to happen on every new node.
+const EVICTION_FRACTION: usize = 10;
+
+/// What happened during a call to `SearchTree::search`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchStats {
+    pub number_of_iterations: u64,
+    /// The iterations left of `SearchConfig::max_iterations` when
+    /// the search was ended early by `SearchConfig::smart_stop`.
+    pub iterations_saved: u64,
+}
 
 #[derive(Clone)]
 pub struct SearchTree {
-    node_metadata: Arc<MetadataMap>,
+    pub(crate) node_metadata: Arc<MetadataMap>,
+    /// Tracked separately because `DHashMap::len` locks every shard,
+    /// includes the nodes stored in `colliding_node_metadata`.
+    pub(crate) number_of_nodes: Arc<AtomicUsize>,
+
+    pub(crate) colliding_node_metadata: Arc<CollisionMap>,
+    pub(crate) number_of_collisions: Arc<AtomicU64>,
+
+    /// Incremented on every search, used by `EvictionPolicy::OldestGeneration`.
+    generation: Arc<AtomicU32>,
+    evicting: Arc<AtomicBool>,
+
+    /// The pool searched on when `SearchConfig::number_of_threads` is set,
+    /// kept so that threads aren't created for every search.
+    thread_pool: Arc<Mutex<Option<Arc<ThreadPool>>>>,
+}
+
+impl Default for SearchTree {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SearchTree {
     pub fn new() -> Self {
         Self {
             node_metadata: Arc::new(DHashMap::default()),
+            number_of_nodes: Arc::new(AtomicUsize::new(0)),
+
+            colliding_node_metadata: Arc::new(DHashMap::default()),
+            number_of_collisions: Arc::new(AtomicU64::new(0)),
+
+            generation: Arc::new(AtomicU32::new(0)),
+            evicting: Arc::new(AtomicBool::new(false)),
+
+            thread_pool: Arc::new(Mutex::new(None)),
         }
     }
 
     /// Explore more of the SearchTree from the node provided
     /// with the given search configuration.
-    pub fn search<N: GameTreeNode<Node = N> + 'static>(&self, node: N, config: SearchConfig) {
-        let number_iterations = Arc::new(AtomicU64::new(0));
-        let _: () = (0..num_cpus::get())
-            .into_par_iter()
-            .map(|_| {
-                let node = node.clone();
-                let task = SearchTask {
-                    tree: self.clone(),
-                    number_iterations: Arc::clone(&number_iterations),
-                    config: config.clone(),
-                };
-                task.run(node);
-                ()
-            })
-            .collect();
+    ///
+    /// Leaf nodes are evaluated with random rollouts.
+    pub fn search<N: GameTreeNode<Node = N> + 'static>(
+        &self,
+        node: N,
+        config: SearchConfig,
+    ) -> SearchStats {
+        let evaluator = RandomRollout {
+            record_actions: config.rave_equivalence.is_some(),
+        };
+        self.search_with_evaluator(node, config, &evaluator)
+    }
+
+    /// Explore more of the SearchTree from the node provided
+    /// with the given search configuration, using `evaluator`
+    /// to estimate the value of leaf nodes.
+    pub fn search_with_evaluator<N: GameTreeNode<Node = N> + 'static, E: Evaluator<N>>(
+        &self,
+        node: N,
+        config: SearchConfig,
+        evaluator: &E,
+    ) -> SearchStats {
+        self.search_until_stopped(
+            node,
+            config,
+            evaluator,
+            Arc::new(AtomicU64::new(0)),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    /// Start searching from `node` on a background thread, returning
+    /// a handle to check the progress of the search or stop it early.
+    pub fn start_search<N: GameTreeNode<Node = N> + 'static>(
+        &self,
+        node: N,
+        config: SearchConfig,
+    ) -> SearchHandle {
+        let evaluator = RandomRollout {
+            record_actions: config.rave_equivalence.is_some(),
+        };
+        self.start_search_with_evaluator(node, config, Arc::new(evaluator))
+    }
+
+    /// Start searching from `node` on a background thread using `evaluator`
+    /// to estimate the value of leaf nodes, see `start_search`.
+    pub fn start_search_with_evaluator<
+        N: GameTreeNode<Node = N> + 'static,
+        E: Evaluator<N> + 'static,
+    >(
+        &self,
+        node: N,
+        config: SearchConfig,
+        evaluator: Arc<E>,
+    ) -> SearchHandle {
+        let tree = self.clone();
+        SearchHandle::spawn(move |number_iterations, stop| {
+            tree.search_until_stopped(node, config, &*evaluator, number_iterations, stop);
+        })
+    }
+
+    /// Search from `node` in the background until the handle is stopped, ignoring
+    /// `max_iterations` and `max_duration`. Used to keep searching while waiting
+    /// for the opponent to move, after which the search should be stopped and
+    /// continued from the position reached.
+    pub fn ponder<N: GameTreeNode<Node = N> + 'static>(
+        &self,
+        node: N,
+        config: SearchConfig,
+    ) -> SearchHandle {
+        self.start_search(
+            node,
+            SearchConfig {
+                max_duration: Duration::from_secs(u64::MAX),
+                max_iterations: u64::MAX,
+                ..config
+            },
+        )
+    }
+
+    /// Search until the limits of `config` are reached or `stop` is set,
+    /// counting completed iterations in `number_iterations`.
+    fn search_until_stopped<N: GameTreeNode<Node = N> + 'static, E: Evaluator<N>>(
+        &self,
+        node: N,
+        config: SearchConfig,
+        evaluator: &E,
+        number_iterations: Arc<AtomicU64>,
+        stop: Arc<AtomicBool>,
+    ) -> SearchStats {
+        let stopped_early = Arc::new(AtomicBool::new(false));
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let root_hash = hash(&node);
+        let root_player = node.current_player();
+        let config = SearchConfig {
+            // Proofs are only defined for two players.
+            solver: config.solver && node.number_of_players() == 2,
+            // Root parallel tasks only see their private statistics, which
+            // can favour a different child than the merged statistics.
+            smart_stop: config.smart_stop
+                && (config.seed.is_some() || config.parallelism != Parallelism::Root),
+            ..config
+        };
+
+        let mut rand = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let root_noise = match (config.root_noise, node.calculate_state()) {
+            (Some(root_noise), NodeState::HasChildren(children)) => Some((
+                root_noise.epsilon,
+                Arc::new(root_noise.sample(children.len(), &mut rand)),
+            )),
+            _ => None,
+        };
+
+        let new_task = |tree: SearchTree| SearchTask {
+            tree,
+            number_iterations: Arc::clone(&number_iterations),
+            stop: Arc::clone(&stop),
+            stopped_early: Arc::clone(&stopped_early),
+            config: config.clone(),
+            evaluator,
+
+            generation,
+            root_hash,
+            root_player,
+            root_noise: root_noise.clone(),
+        };
+
+        let thread_pool = config
+            .number_of_threads
+            .map(|number_of_threads| self.thread_pool(number_of_threads.max(1)));
+        match (config.seed, config.parallelism) {
+            // A single task on this thread when seeded, so the order
+            // of every random choice is the same between searches.
+            (Some(_), _) | (None, Parallelism::SingleThreaded) => {
+                new_task(self.clone()).run(node, rand)
+            }
+            (None, Parallelism::Tree) => run_on_each_thread(thread_pool.as_deref(), || {
+                new_task(self.clone()).run(node.clone(), StdRng::from_entropy())
+            }),
+            (None, Parallelism::Root) => run_on_each_thread(thread_pool.as_deref(), || {
+                let private_tree = SearchTree::new();
+                new_task(private_tree.clone()).run(node.clone(), StdRng::from_entropy());
+                new_task(self.clone()).merge_root_statistics(&private_tree, &node);
+            }),
+        }
+
+        let number_of_iterations = number_iterations.load(Ordering::SeqCst);
+        let iterations_saved = if stopped_early.load(Ordering::SeqCst) {
+            config.max_iterations.saturating_sub(number_of_iterations)
+        } else {
+            0
+        };
+        SearchStats {
+            number_of_iterations,
+            iterations_saved,
+        }
     }
 
     pub fn number_of_fully_expanded_nodes<N: GameTreeNode<Node = N> + 'static>(
@@ -50,11 +253,22 @@ impl SearchTree {
         node: N,
     ) -> u64 {
         let mut count = 0;
+        // Nodes reachable by more than one path are only counted once.
+        let mut seen = HashSet::new();
         let mut queue = vec![node];
         while let Some(node) = queue.pop() {
+            if !seen.insert(hash(&node)) {
+                continue;
+            }
+
+            // Chance nodes are never fully expanded, since their outcomes are sampled.
+            if let NodeState::Chance(outcomes) = node.calculate_state() {
+                queue.extend(outcomes.into_iter().map(|(outcome, _weight)| outcome));
+                continue;
+            }
+
             let is_fully_expanded = self
-                .node_metadata
-                .get(&hash(&node))
+                .get_metadata(&node)
                 .map(|meta| meta.is_fully_expanded())
                 .unwrap_or(false);
             if !is_fully_expanded {
@@ -69,195 +283,2001 @@ impl SearchTree {
         count
     }
 
+    /// Make `new_root` the root of the tree, keeping the statistics of every node
+    /// reachable from it and dropping everything else. Returns the number of nodes dropped.
+    ///
+    /// Should not be called while a search is running on this tree.
+    pub fn advance_root<N: GameTreeNode<Node = N>>(&self, new_root: &N) -> usize {
+        let mut reachable = HashSet::new();
+        let mut queue = vec![new_root.clone()];
+        while let Some(node) = queue.pop() {
+            if !reachable.insert(hash(&node)) {
+                continue;
+            }
+
+            let is_visited = self
+                .get_metadata(&node)
+                .map(|meta| meta.is_visited())
+                .unwrap_or(false);
+            if !is_visited {
+                continue;
+            }
+
+            match node.calculate_state() {
+                NodeState::HasChildren(children) => queue.extend(children),
+                NodeState::Chance(outcomes) => {
+                    queue.extend(outcomes.into_iter().map(|(outcome, _weight)| outcome))
+                }
+                NodeState::Reward(_) => (),
+            }
+        }
+
+        let number_of_nodes = self.node_metadata.len() + self.colliding_node_metadata.len();
+        self.node_metadata
+            .retain(|node_hash, _| reachable.contains(node_hash));
+        self.colliding_node_metadata
+            .retain(|(node_hash, _), _| reachable.contains(node_hash));
+        let remaining_nodes = self.node_metadata.len() + self.colliding_node_metadata.len();
+        self.number_of_nodes
+            .store(remaining_nodes, Ordering::SeqCst);
+        number_of_nodes - remaining_nodes
+    }
+
+    /// The pool with `number_of_threads` threads, which is built the first time
+    /// it's needed and reused by later searches with the same number of threads.
+    fn thread_pool(&self, number_of_threads: usize) -> Arc<ThreadPool> {
+        let mut thread_pool = self
+            .thread_pool
+            .lock()
+            .expect("thread pool lock isn't poisoned");
+        match &*thread_pool {
+            Some(pool) if pool.current_num_threads() == number_of_threads => Arc::clone(pool),
+            _ => {
+                let pool = Arc::new(
+                    ThreadPoolBuilder::new()
+                        .num_threads(number_of_threads)
+                        .build()
+                        .expect("building the search thread pool"),
+                );
+                *thread_pool = Some(Arc::clone(&pool));
+                pool
+            }
+        }
+    }
+
+    /// The number of nodes which have statistics stored in the tree,
+    /// including nodes stored separately because of hash collisions.
+    pub fn number_of_nodes(&self) -> usize {
+        self.number_of_nodes.load(Ordering::SeqCst)
+    }
+
+    /// The number of distinct nodes found to share a hash with another node,
+    /// only detected when searching with `SearchConfig::verify_hashes`.
+    pub fn number_of_collisions(&self) -> u64 {
+        self.number_of_collisions.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn get_metadata(&self, node: &impl GameTreeNode) -> Option<Arc<NodeMetadata>> {
+        self.find_metadata(hash(node), || check_hash(node))
+    }
+
+    /// Find the metadata stored for `node_hash`, using the check hash to
+    /// resolve collisions if the stored metadata was created with one.
+    fn find_metadata(
+        &self,
+        node_hash: u64,
+        check_hash: impl FnOnce() -> u64,
+    ) -> Option<Arc<NodeMetadata>> {
+        let metadata = self
+            .node_metadata
+            .get(&node_hash)
+            .map(|meta| Arc::clone(&meta))?;
+
+        match metadata.check_hash() {
+            Some(stored_check_hash) => {
+                let check_hash = check_hash();
+                if stored_check_hash == check_hash {
+                    Some(metadata)
+                } else {
+                    self.colliding_node_metadata
+                        .get(&(node_hash, check_hash))
+                        .map(|meta| Arc::clone(&meta))
+                }
+            }
+            None => Some(metadata),
+        }
+    }
+
+    /// Get or insert the metadata for `node_hash`, if `check_hash` is provided
+    /// any collision with a different node is counted and stored separately.
+    fn insert_metadata(&self, node_hash: u64, check_hash: Option<u64>) -> Arc<NodeMetadata> {
+        let metadata = self
+            .node_metadata
+            .get_or_insert_with(&node_hash, || {
+                self.number_of_nodes.fetch_add(1, Ordering::SeqCst);
+                Arc::new(NodeMetadata::new(check_hash))
+            })
+            .clone();
+
+        match (metadata.check_hash(), check_hash) {
+            (Some(stored_check_hash), Some(check_hash)) if stored_check_hash != check_hash => self
+                .colliding_node_metadata
+                .get_or_insert_with(&(node_hash, check_hash), || {
+                    self.number_of_nodes.fetch_add(1, Ordering::SeqCst);
+                    self.number_of_collisions.fetch_add(1, Ordering::SeqCst);
+                    Arc::new(NodeMetadata::new(Some(check_hash)))
+                })
+                .clone(),
+            _ => metadata,
+        }
+    }
+
+    /// Evict nodes in the order given by `policy` until at most
+    /// `target` remain, nodes with `protected_hash` are never evicted.
+    /// Colliding nodes are evicted along with the node sharing their hash,
+    /// since they can't be found without it.
+    ///
+    /// Returns immediately if another thread is already evicting.
+    fn evict(&self, target: usize, policy: EvictionPolicy, protected_hash: u64) {
+        if self
+            .evicting
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
+
+        // Colliding nodes are identified by their check hash as well.
+        let mut candidates = self
+            .node_metadata
+            .iter()
+            .map(|entry| ((*entry.key(), None), entry.value().clone()))
+            .chain(
+                self.colliding_node_metadata
+                    .iter()
+                    .map(|entry| ((entry.key().0, Some(entry.key().1)), entry.value().clone())),
+            )
+            .filter(|((node_hash, _), _)| *node_hash != protected_hash)
+            .map(|(key, meta)| (key, meta.number_of_visits(), meta.generation()))
+            .collect::<Vec<_>>();
+        match policy {
+            EvictionPolicy::LeastVisited => {
+                candidates.sort_by_key(|&(key, visits, generation)| (visits, generation, key))
+            }
+            EvictionPolicy::OldestGeneration => {
+                candidates.sort_by_key(|&(key, visits, generation)| (generation, visits, key))
+            }
+        }
+
+        let number_to_evict = self.number_of_nodes().saturating_sub(target);
+        let mut evicted_hashes = HashSet::new();
+        for ((node_hash, check_hash), _, _) in candidates.into_iter().take(number_to_evict) {
+            let removed = match check_hash {
+                None => {
+                    evicted_hashes.insert(node_hash);
+                    self.node_metadata.remove(&node_hash).is_some()
+                }
+                Some(check_hash) => self
+                    .colliding_node_metadata
+                    .remove(&(node_hash, check_hash))
+                    .is_some(),
+            };
+            if removed {
+                self.number_of_nodes.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        if !evicted_hashes.is_empty() && !self.colliding_node_metadata.is_empty() {
+            self.colliding_node_metadata.retain(|(node_hash, _), _| {
+                let orphaned = evicted_hashes.contains(node_hash);
+                if orphaned {
+                    self.number_of_nodes.fetch_sub(1, Ordering::SeqCst);
+                }
+                !orphaned
+            });
+        }
+
+        self.evicting.store(false, Ordering::SeqCst);
+    }
+
+    /// Select the child with the most visits, children which are proven
+    /// wins are always selected first and proven losses last.
     pub fn select_most_visited_child<N: GameTreeNode>(&self, children: Vec<N>) -> (u32, N) {
         children
             .into_iter()
-            .map(|c| (self.get_number_of_visits(&c), c))
-            .max_by_key(|(number_of_visits, _c)| *number_of_visits)
+            .map(|c| (self.get_number_of_visits(&c), self.get_proof(&c), c))
+            .max_by_key(|(number_of_visits, proof, _c)| (proof_rank(*proof), *number_of_visits))
+            .map(|(number_of_visits, _proof, c)| (number_of_visits, c))
             .expect("array is not empty")
     }
 
+    /// The statistics of every child of `root`, most preferred first
+    /// (in the same order as `select_most_visited_child`).
+    ///
+    /// Empty if `root` is terminal or a chance node.
+    pub fn analyze<N: GameTreeNode<Node = N>>(&self, root: &N) -> Vec<MoveAnalysis<N>> {
+        let children = match root.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            NodeState::Reward(_) | NodeState::Chance(_) => return vec![],
+        };
+
+        let mut analysis = root
+            .legal_actions()
+            .into_iter()
+            .zip(children)
+            .map(|(action, node)| {
+                let metadata = self.get_metadata(&node);
+                let (number_of_visits, mean_value, prior, proof) = match &metadata {
+                    Some(meta) => {
+                        let number_of_visits = meta.number_of_visits();
+                        let mean_value = if number_of_visits > 0 {
+                            meta.total_reward() / f64::from(number_of_visits)
+                        } else {
+                            0.0
+                        };
+                        (number_of_visits, mean_value, meta.prior(), meta.proof())
+                    }
+                    None => (0, 0.0, 0.0, None),
+                };
+                MoveAnalysis {
+                    principal_variation: self.principal_variation(&node),
+                    action,
+                    node,
+                    number_of_visits,
+                    mean_value,
+                    prior,
+                    proof,
+                }
+            })
+            .collect::<Vec<_>>();
+        analysis.sort_by(|a, b| {
+            let a_key = (
+                proof_rank(a.proof),
+                a.number_of_visits,
+                OrderedFloat(a.mean_value),
+            );
+            let b_key = (
+                proof_rank(b.proof),
+                b.number_of_visits,
+                OrderedFloat(b.mean_value),
+            );
+            b_key.cmp(&a_key)
+        });
+        analysis
+    }
+
+    /// Follow the most visited children from `node` for as long as they
+    /// have been visited, stopping at chance nodes.
+    fn principal_variation<N: GameTreeNode<Node = N>>(&self, node: &N) -> Vec<N> {
+        let mut variation = vec![];
+        // Guards against games which can repeat positions.
+        let mut seen = HashSet::new();
+        seen.insert(hash(node));
+
+        let mut current = node.clone();
+        while let NodeState::HasChildren(children) = current.calculate_state() {
+            let (number_of_visits, best) = self.select_most_visited_child(children);
+            if number_of_visits == 0 || !seen.insert(hash(&best)) {
+                break;
+            }
+            variation.push(best.clone());
+            current = best;
+        }
+        variation
+    }
+
+    /// The fraction of the visits of `children` which went to each child, the
+    /// policy target when training. Uniform if none of the children are visited.
+    pub fn visit_distribution<N: GameTreeNode>(&self, children: &[N]) -> Vec<f64> {
+        self.visit_distribution_with_temperature(children, 1.0)
+    }
+
+    /// The probability of choosing each child when sampling with `temperature`,
+    /// proportional to `visits^(1 / temperature)`. A temperature of 0 is greedy.
+    pub fn visit_distribution_with_temperature<N: GameTreeNode>(
+        &self,
+        children: &[N],
+        temperature: f64,
+    ) -> Vec<f64> {
+        let visits = children
+            .iter()
+            .map(|c| self.get_number_of_visits(c))
+            .collect::<Vec<_>>();
+        apply_temperature(&visits, temperature)
+    }
+
+    /// Sample a child from the visit distribution with `temperature`,
+    /// a temperature of 0 is the same as `select_most_visited_child`.
+    pub fn select_child_with_temperature<N: GameTreeNode>(
+        &self,
+        children: Vec<N>,
+        temperature: f64,
+        rand: &mut impl Rng,
+    ) -> N {
+        if temperature <= 0.0 {
+            return self.select_most_visited_child(children).1;
+        }
+
+        let distribution = self.visit_distribution_with_temperature(&children, temperature);
+        let index = WeightedIndex::new(&distribution)
+            .expect("distribution is not empty")
+            .sample(rand);
+        children
+            .into_iter()
+            .nth(index)
+            .expect("index is within children")
+    }
+
+    /// The value of the node proven by searching with `SearchConfig::solver`, from
+    /// the perspective of the player who made the move arriving at the node.
+    pub fn get_proof<N: GameTreeNode>(&self, node: &N) -> Option<Proof> {
+        self.get_metadata(node).and_then(|meta| meta.proof())
+    }
+
     pub fn get_number_of_visits<N: GameTreeNode>(&self, node: &N) -> u32 {
-        self.node_metadata
-            .get(&hash(node))
+        self.get_metadata(node)
             .map(|meta| meta.number_of_visits())
             .unwrap_or(0)
     }
 }
 
-struct SearchTask {
+struct SearchTask<'e, E> {
     tree: SearchTree,
     number_iterations: Arc<AtomicU64>,
+    /// Set to stop the search before the limits of `config` are reached.
+    stop: Arc<AtomicBool>,
+    /// Set once `SearchConfig::smart_stop` decides that the most visited child can't change.
+    stopped_early: Arc<AtomicBool>,
     config: SearchConfig,
+    evaluator: &'e E,
+
+    generation: u32,
+    root_hash: u64,
+    /// The player to move at the root, used by `Backup::Paranoid`.
+    root_player: usize,
+    /// Epsilon and the noise sampled for each child of the root.
+    root_noise: Option<(f64, Arc<Vec<f64>>)>,
 }
 
-impl SearchTask {
-    fn run<N: GameTreeNode<Node = N>>(self, node: N) {
+/// A node passed through while descending the tree.
+struct Visit {
+    metadata: Arc<NodeMetadata>,
+
+    /// The player who took the action arriving to this node.
+    mover: usize,
+    /// The player choosing between the children of this node.
+    player: usize,
+    /// The `GameAction::index` of the action arriving to this
+    /// node, only recorded when searching with RAVE.
+    action_index: Option<usize>,
+    /// The metadata of the children when the node was descended through,
+    /// only recorded when searching with the solver or RAVE.
+    children: Vec<Arc<NodeMetadata>>,
+    /// The `GameAction::index` of the action to each of `children`, only recorded when searching with RAVE.
+    children_action_indices: Vec<Option<usize>>,
+    /// The weight of each outcome if the node is a chance node, whose `children` (the
+    /// outcomes) are only recorded when searching with the solver or `ChanceBackup::Expectation`.
+    outcome_weights: Option<Vec<f64>>,
+}
+
+impl Visit {
+    /// The proof of the node given the proofs of its recorded `children`,
+    /// from the perspective of the player who moved to it.
+    fn proof_from_children(&self) -> Option<Proof> {
+        if self.outcome_weights.is_some() {
+            // Outcomes are from the same perspective as the chance node but aren't
+            // chosen, so it is only proven once every outcome has the same proof.
+            let proof = self.children.first().and_then(|c| c.proof());
+            if self.children.iter().all(|c| c.proof() == proof) {
+                proof
+            } else {
+                None
+            }
+        } else {
+            // The proof is from the perspective of the player choosing at this node,
+            // which is the opponent of the player who moved here unless they move twice.
+            Proof::for_chooser_of(&self.children).map(|proof| {
+                if self.mover == self.player {
+                    proof
+                } else {
+                    proof.opposite()
+                }
+            })
+        }
+    }
+}
+
+/// A child of the node being descended through.
+struct Child<N> {
+    /// The index of the child, in the order of `GameTreeNode::child`.
+    index: usize,
+    /// `None` until the child is chosen if the node generates its children lazily.
+    node: Option<N>,
+    metadata: Arc<NodeMetadata>,
+    /// The prior used for selection, including any noise at the root.
+    prior: f64,
+    /// The `GameAction::index` of the action to the child, only recorded when searching with RAVE.
+    action_index: Option<usize>,
+}
+
+/// Where a single descent from the root ended up.
+enum Descent<N> {
+    /// A terminal node was reached, the reward can be backed up immediately.
+    Terminal {
+        visited: Vec<Visit>,
+        rewards: Vec<f64>,
+    },
+    /// A leaf of the search tree was reached and needs to be evaluated.
+    Leaf {
+        visited: Vec<Visit>,
+        node: N,
+        children: Vec<N>,
+        /// The leaf is already waiting on an evaluation from another descent.
+        already_pending: bool,
+    },
+    /// The search ran out of time or was stopped before reaching a leaf.
+    Stopped,
+}
+
+/// A descent waiting on the evaluation of its leaf node.
+struct PendingLeaf<N> {
+    visited: Vec<Visit>,
+    node: N,
+    children: Vec<N>,
+}
+
+impl<'e, E> SearchTask<'e, E> {
+    fn run<N: GameTreeNode<Node = N>>(self, node: N, mut rand: StdRng)
+    where
+        E: Evaluator<N>,
+    {
         let start = Instant::now();
-        let mut rand = rand::thread_rng();
+        let root_metadata = self.load_metadata(&node);
+        let root_children = match (self.config.smart_stop, node.calculate_state()) {
+            (true, NodeState::HasChildren(children)) => children
+                .iter()
+                .map(|child| self.load_metadata(child))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
 
-        macro_rules! load_metadata {
-            ($node:expr) => {
-                Self::load_metadata($node, &self.tree.node_metadata)
-            };
+        let batch_size = self.config.batch_size.max(1);
+        'run: loop {
+            // Collect leaves from multiple descents so they can be evaluated
+            // together, virtual loss keeps the descents from all choosing the same path.
+            let mut batch = vec![];
+            let mut stopped = false;
+            while batch.len() < batch_size {
+                let number_iterations =
+                    self.number_iterations.load(Ordering::SeqCst) + batch.len() as u64;
+                if number_iterations > self.config.max_iterations {
+                    break;
+                }
+
+                // There is nothing left to learn once the root is solved.
+                if self.config.solver && root_metadata.proof().is_some() {
+                    break;
+                }
+
+                if self.stopped_early.load(Ordering::SeqCst)
+                    || self.is_decided(&root_children, number_iterations)
+                {
+                    self.stopped_early.store(true, Ordering::SeqCst);
+                    break;
+                }
+
+                match self.descend(&node, start, &mut rand) {
+                    Descent::Terminal { visited, rewards } => {
+                        if self.config.solver {
+                            self.propagate_proofs(&visited, &rewards);
+                        }
+                        if self.config.rave_equivalence.is_some() {
+                            self.backup_amaf(&visited, &rewards, &[]);
+                        }
+                        self.backup(visited, &rewards);
+                    }
+                    Descent::Leaf {
+                        visited,
+                        node,
+                        children,
+                        already_pending,
+                    } => {
+                        // Evaluate what has been collected so far instead of
+                        // evaluating the same leaf twice in one batch.
+                        if already_pending && !batch.is_empty() {
+                            self.revert_virtual_loss(&visited);
+                            break;
+                        }
+
+                        batch.push(PendingLeaf {
+                            visited,
+                            node,
+                            children,
+                        });
+                    }
+                    Descent::Stopped => {
+                        stopped = true;
+                        break;
+                    }
+                }
+            }
+
+            let finished = batch.is_empty() || stopped;
+            self.evaluate_and_backup(batch, &mut rand);
+            if finished {
+                break 'run;
+            }
         }
+    }
 
+    /// Whether the most visited of `root_children` can't be overtaken
+    /// in the iterations remaining of `max_iterations`.
+    fn is_decided(&self, root_children: &[Arc<NodeMetadata>], number_iterations: u64) -> bool {
+        let remaining_iterations = self.config.max_iterations.saturating_sub(number_iterations);
+        leader_is_decided(
+            root_children.iter().map(|child| child.number_of_visits()),
+            remaining_iterations,
+        )
+    }
+
+    fn descend<N: GameTreeNode<Node = N>>(
+        &self,
+        root: &N,
+        start: Instant,
+        rand: &mut impl Rng,
+    ) -> Descent<N> {
         #[derive(Debug, PartialEq, Clone, Copy)]
         enum State {
             NodesFullyExpanded,
             ChooseSimulationStart,
-            InSimulation,
         }
 
-        'run: loop {
-            if self.number_iterations.load(Ordering::SeqCst) > self.config.max_iterations {
-                break 'run;
+        let mut visited = vec![];
+        let mut node = root.clone();
+        let mut metadata = self.load_metadata(root);
+        // Assume that players take turns in order before the root.
+        let number_of_players = root.number_of_players();
+        let mut mover = (root.current_player() + number_of_players - 1) % number_of_players;
+        let mut action_index = None;
+
+        let mut state = State::NodesFullyExpanded;
+        loop {
+            if start.elapsed() > self.config.max_duration || self.stop.load(Ordering::SeqCst) {
+                self.revert_virtual_loss(&visited);
+                return Descent::Stopped;
             }
 
-            struct Current<N> {
-                node: N,
-                metadata: Arc<NodeMetadata>,
+            // Nodes are only descended through after they are visited, so an
+            // unvisited node with virtual loss is a leaf waiting on evaluation.
+            let already_pending = !metadata.is_visited() && metadata.has_virtual_loss();
+            metadata.add_virtual_loss(self.config.virtual_loss);
+            visited.push(Visit {
+                metadata: Arc::clone(&metadata),
+                mover,
+                player: node.current_player(),
+                action_index,
+                children: vec![],
+                children_action_indices: vec![],
+                outcome_weights: None,
+            });
 
-                /// The action arriving to this node was from
-                /// a node whose turn was 'self'.
-                parent_was_self: bool,
-                /// The node was reached through State::InSimulation
-                chosen_from_simulation: bool,
+            // The value of a proven node is already known, so there is no need to
+            // descend further. Proofs of the root are handled by stopping the search.
+            if let (true, Some(proof)) = (self.config.solver && visited.len() > 1, metadata.proof())
+            {
+                let rewards = two_player_rewards(mover, proof.reward());
+                return Descent::Terminal { visited, rewards };
             }
 
-            let mut visited = vec![];
-            let mut current = Current {
-                node: node.clone(),
-                metadata: load_metadata!(&node),
-                parent_was_self: false,
-                chosen_from_simulation: false,
+            // Once a node which generates its children lazily has been descended through,
+            // its children are found by the hashes stored with it and only the chosen
+            // child is generated.
+            let stored_children_hashes = if node.generates_children_lazily() {
+                metadata.children_hashes()
+            } else {
+                None
             };
+            let children = match stored_children_hashes {
+                Some(children_hashes) => children_hashes
+                    .iter()
+                    .map(|&(node_hash, check_hash)| {
+                        (None, self.load_metadata_by_hash(node_hash, || check_hash))
+                    })
+                    .collect::<Vec<_>>(),
+                None => match node.calculate_state() {
+                    NodeState::Reward(rewards) => {
+                        return Descent::Terminal {
+                            visited,
+                            rewards: rewards.into_iter().map(|reward| reward as f64).collect(),
+                        };
+                    }
+                    NodeState::HasChildren(children) => {
+                        debug_assert!(!children.is_empty());
 
-            let mut state = State::NodesFullyExpanded;
-            let reward = loop {
-                if start.elapsed() > self.config.max_duration {
-                    break 'run;
+                        // The first time a node is reached it is a leaf of the
+                        // search tree, so its value is estimated by the evaluator.
+                        if !metadata.is_visited() {
+                            return Descent::Leaf {
+                                visited,
+                                node,
+                                children,
+                                already_pending,
+                            };
+                        }
+
+                        if node.generates_children_lazily() {
+                            metadata.set_children_hashes(
+                                children.iter().map(|c| (hash(c), check_hash(c))).collect(),
+                            );
+                        }
+                        children
+                            .into_iter()
+                            .map(|c| {
+                                let metadata = self.load_metadata(&c);
+                                (Some(c), metadata)
+                            })
+                            .collect::<Vec<_>>()
+                    }
+                    NodeState::Chance(outcomes) => {
+                        let (outcomes, weights): (Vec<_>, Vec<_>) = outcomes.into_iter().unzip();
+                        let index = WeightedIndex::new(&weights)
+                            .expect("outcomes have valid weights")
+                            .sample(rand);
+
+                        let visit = visited.last_mut().expect("node was visited");
+                        if self.config.solver
+                            || self.config.chance_backup == ChanceBackup::Expectation
+                        {
+                            visit.children =
+                                outcomes.iter().map(|o| self.load_metadata(o)).collect();
+                        }
+                        visit.outcome_weights = Some(weights);
+                        if let (true, Some(proof)) =
+                            (self.config.solver, visit.proof_from_children())
+                        {
+                            let rewards = two_player_rewards(mover, proof.reward());
+                            return Descent::Terminal { visited, rewards };
+                        }
+
+                        // Chance nodes are never leaves, the descent continues with the sampled
+                        // outcome. It is treated as a move by the player who moved to the chance
+                        // node, so `mover` is unchanged.
+                        metadata = match visit.children.get(index) {
+                            Some(metadata) => Arc::clone(metadata),
+                            None => self.load_metadata(&outcomes[index]),
+                        };
+                        node = outcomes
+                            .into_iter()
+                            .nth(index)
+                            .expect("index is within outcomes");
+                        action_index = None;
+                        continue;
+                    }
+                },
+            };
+
+            if !metadata.are_children_priors_set() {
+                // Without any other information, all children are equally likely.
+                let prior = 1.0 / children.len() as f64;
+                for (_, meta) in &children {
+                    meta.set_prior(prior);
                 }
+                metadata.set_children_priors_set();
+            }
 
-                let mut children = match current.node.calculate_state() {
-                    NodeState::Reward(reward) => {
-                        // Ensure that current node is marked as visited
-                        // before reporting the reward.
-                        visited.push(current);
-                        break reward;
+            // Noise is only mixed into the priors of the children of the root.
+            let root_noise = match (&self.root_noise, visited.len()) {
+                (Some(root_noise), 1) => Some(root_noise),
+                _ => None,
+            };
+            let rave = self.config.rave_equivalence.is_some();
+            // The child at each index is reached by the legal action at the same index.
+            let action_indices = if rave {
+                node.legal_actions().iter().map(GameAction::index).collect()
+            } else {
+                vec![]
+            };
+            let mut children = children
+                .into_iter()
+                .enumerate()
+                .map(|(i, (node, metadata))| {
+                    let mut prior = metadata.prior();
+                    if let Some((epsilon, noise)) = root_noise {
+                        prior = (1.0 - epsilon) * prior + epsilon * noise[i];
                     }
-                    NodeState::HasChildren(children) => children
-                        .into_iter()
-                        .map(|c| {
-                            let metadata = load_metadata!(&c);
-                            (c, metadata)
-                        })
-                        .collect::<Vec<_>>(),
-                };
 
-                debug_assert!(!children.is_empty());
-
-                let mut chosen_from_simulation = false;
-                let chosen_child = loop {
-                    match state {
-                        State::NodesFullyExpanded => {
-                            if !current.metadata.is_fully_expanded() {
-                                // If cached check fails, ensure that it is
-                                // truly not fully expanded.
-                                let all_children_visited =
-                                    children.iter().all(|(_, meta)| meta.is_visited());
-                                if all_children_visited {
-                                    current.metadata.set_fully_expanded();
-                                } else {
-                                    state = State::ChooseSimulationStart;
-                                    continue;
-                                }
-                            }
+                    Child {
+                        index: i,
+                        node,
+                        metadata,
+                        prior,
+                        action_index: action_indices.get(i).cloned(),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if self.config.solver || rave {
+                let visit = visited.last_mut().expect("node was visited");
+                visit.children = children.iter().map(|c| Arc::clone(&c.metadata)).collect();
+                if rave {
+                    visit.children_action_indices =
+                        children.iter().map(|c| c.action_index).collect();
+                }
+
+                // The children may have been proven through transpositions,
+                // by descents which didn't pass through this node.
+                if let (true, Some(proof)) = (self.config.solver, visit.proof_from_children()) {
+                    let rewards = two_player_rewards(mover, proof.reward());
+                    return Descent::Terminal { visited, rewards };
+                }
+            }
+
+            let mut proven_win_index = None;
+            if self.config.solver {
+                // Children are proven from the perspective of the player choosing between
+                // them, always choose a proven win. Otherwise prefer unproven children, which
+                // are needed to prove this node, and then draws over losses. Proven children
+                // end the descent immediately, so choosing them would starve their siblings.
+                // Done before progressive widening, so that only the preferred children
+                // are widened into.
+                proven_win_index = children
+                    .iter()
+                    .position(|c| c.metadata.proof() == Some(Proof::Win));
+                if proven_win_index.is_none() {
+                    let preferred = if children.iter().any(|c| c.metadata.proof().is_none()) {
+                        None
+                    } else {
+                        Some(Proof::Draw)
+                    };
+                    if children.iter().any(|c| c.metadata.proof() == preferred) {
+                        children.retain(|c| c.metadata.proof() == preferred);
+                    }
+                }
+            }
+
+            let number_of_children = children.len();
+            if let (None, Some(widening)) = (proven_win_index, self.config.progressive_widening) {
+                // Stable, so children with equal priors stay in the order of the game.
+                children.sort_by_key(|c| Reverse(OrderedFloat(c.prior)));
+                children.truncate(widening.number_of_children(metadata.number_of_visits()));
+            }
+            let considering_all_children = children.len() == number_of_children;
+
+            let chosen_child = loop {
+                if let Some(index) = proven_win_index {
+                    break children.remove(index);
+                }
 
-                            break children
-                                .into_iter()
-                                .max_by_key(|(_, meta)| {
-                                    OrderedFloat(
-                                        meta.uct(&current.metadata, self.config.exploration_factor),
-                                    )
-                                })
-                                .expect("array is not empty");
+                match state {
+                    State::NodesFullyExpanded => {
+                        if !self.config.selection.visits_every_child_first() {
+                            // An unvisited child is evaluated once it is chosen.
+                            break self.select(&metadata, children, rand);
                         }
-                        State::ChooseSimulationStart => {
-                            let non_visited_indices = children
-                                .iter()
-                                .enumerate()
-                                .filter_map(|(i, (_, meta))| {
-                                    if meta.is_visited() {
-                                        return None;
-                                    }
-
-                                    Some(i)
-                                })
-                                .collect::<Vec<_>>();
-
-                            if non_visited_indices.is_empty() {
-                                state = State::NodesFullyExpanded;
+
+                        if !metadata.is_fully_expanded() {
+                            // If cached check fails, ensure that it is
+                            // truly not fully expanded.
+                            let all_children_visited =
+                                children.iter().all(|c| c.metadata.is_visited());
+                            if !all_children_visited {
+                                state = State::ChooseSimulationStart;
                                 continue;
                             }
-
-                            let index = non_visited_indices.choose(&mut rand).expect("not empty");
-                            state = State::InSimulation;
-                            break children.remove(*index);
+                            // With progressive widening more children
+                            // may be considered in later descents.
+                            if considering_all_children {
+                                metadata.set_fully_expanded();
+                            }
                         }
-                        State::InSimulation => {
-                            chosen_from_simulation = true;
-                            break children
-                                .into_iter()
-                                .choose(&mut rand)
-                                .expect("array is not empty");
+
+                        break self.select(&metadata, children, rand);
+                    }
+                    State::ChooseSimulationStart => {
+                        let non_visited_indices = children
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, c)| {
+                                if c.metadata.is_visited() {
+                                    return None;
+                                }
+
+                                Some(i)
+                            })
+                            .collect::<Vec<_>>();
+
+                        if non_visited_indices.is_empty() {
+                            state = State::NodesFullyExpanded;
+                            continue;
                         }
+
+                        // Prefer children which are not already waiting on an evaluation.
+                        let not_pending_indices = non_visited_indices
+                            .iter()
+                            .cloned()
+                            .filter(|&i| !children[i].metadata.has_virtual_loss())
+                            .collect::<Vec<_>>();
+                        let index = if not_pending_indices.is_empty() {
+                            non_visited_indices.choose(rand)
+                        } else {
+                            not_pending_indices.choose(rand)
+                        };
+                        break children.remove(*index.expect("not empty"));
                     }
-                };
+                }
+            };
 
-                let parent_was_self = current.node.is_self_turn();
-                visited.push(current);
-                current = Current {
-                    node: chosen_child.0,
-                    metadata: chosen_child.1,
-                    parent_was_self,
-                    chosen_from_simulation,
-                };
+            mover = node.current_player();
+            action_index = chosen_child.action_index;
+            node = match chosen_child.node {
+                Some(child) => child,
+                None => node.child(chosen_child.index),
             };
+            metadata = chosen_child.metadata;
+        }
+    }
+
+    /// The child with the highest score from `SearchConfig::selection`.
+    fn select<N>(
+        &self,
+        parent_metadata: &NodeMetadata,
+        children: Vec<Child<N>>,
+        rand: &mut impl Rng,
+    ) -> Child<N> {
+        let rave_equivalence = self.config.rave_equivalence;
+        let parent_stats = parent_metadata.selection_stats(1.0, rave_equivalence);
+        children
+            .into_iter()
+            .map(|c| {
+                let child_stats = c.metadata.selection_stats(c.prior, rave_equivalence);
+                let score = self.config.selection.score(
+                    &parent_stats,
+                    &child_stats,
+                    self.config.exploration_factor,
+                    rand,
+                );
+                (OrderedFloat(score), c)
+            })
+            .max_by_key(|(score, _c)| *score)
+            .map(|(_score, c)| c)
+            .expect("array is not empty")
+    }
+
+    fn evaluate_and_backup<N: GameTreeNode<Node = N>>(
+        &self,
+        batch: Vec<PendingLeaf<N>>,
+        rand: &mut StdRng,
+    ) where
+        E: Evaluator<N>,
+    {
+        if batch.is_empty() {
+            return;
+        }
+
+        let (visits, leaves): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|leaf| (leaf.visited, (leaf.node, leaf.children)))
+            .unzip();
+
+        let evaluations = self.evaluator.evaluate_batch(&leaves, rand);
+        debug_assert_eq!(evaluations.len(), leaves.len());
+
+        for ((visited, (_node, children)), evaluation) in
+            visits.into_iter().zip(leaves).zip(evaluations)
+        {
+            debug_assert_eq!(evaluation.priors.len(), children.len());
+            for (child, prior) in children.iter().zip(evaluation.priors) {
+                self.load_metadata(child).set_prior(prior);
+            }
+
+            let leaf = visited.last().expect("leaf was visited");
+            leaf.metadata.set_children_priors_set();
+
+            if self.config.rave_equivalence.is_some() {
+                self.backup_amaf(&visited, &evaluation.values, &evaluation.played_actions);
+            }
+            self.backup(visited, &evaluation.values);
+        }
+    }
 
-            // After search has reached some terminal node with a reward,
-            // back-propagate the reward along any fully expanded nodes.
-            for Current {
-                node: _node,
-                metadata,
-                parent_was_self,
-                chosen_from_simulation,
-            } in visited
+    /// Back-propagate the reward along the visited nodes,
+    /// removing the virtual loss added while descending.
+    fn backup(&self, visited: Vec<Visit>, rewards: &[f64]) {
+        // Bottom-up, so the expectation of a chance node includes the outcome just backed up.
+        for visit in visited.into_iter().rev() {
+            // Nodes record the reward of the player who moved to them.
+            let mut reward = self.reward_for(visit.mover, rewards);
+            if let (ChanceBackup::Expectation, Some(weights)) =
+                (self.config.chance_backup, &visit.outcome_weights)
             {
-                // Don't record nodes where were reached through simulation.
-                if chosen_from_simulation {
-                    continue;
+                reward = expected_reward(&visit.children, weights).unwrap_or(reward);
+            }
+            visit.metadata.record_result(reward);
+            visit.metadata.remove_virtual_loss(self.config.virtual_loss);
+        }
+
+        self.number_iterations.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// The reward of `player` out of the `rewards` of every player, according to `SearchConfig::backup`.
+    fn reward_for(&self, player: usize, rewards: &[f64]) -> f64 {
+        match self.config.backup {
+            Backup::MaxN => rewards[player],
+            Backup::Paranoid if player == self.root_player => rewards[player],
+            Backup::Paranoid => -rewards[self.root_player],
+        }
+    }
+
+    /// Record the reward on every child of the visited nodes whose action was taken later
+    /// in the simulation by the player choosing between them, the actions taken are
+    /// those arriving at the visited nodes followed by `played_actions`.
+    fn backup_amaf(&self, visited: &[Visit], rewards: &[f64], played_actions: &[PlayedAction]) {
+        // The actions taken by each player.
+        let mut taken = vec![HashSet::new(); rewards.len()];
+        for action in played_actions {
+            taken[action.player].insert(action.action_index);
+        }
+
+        for visit in visited.iter().rev() {
+            let chooser_reward = self.reward_for(visit.player, rewards);
+            let taken_by_chooser = &taken[visit.player];
+            for (child, action_index) in visit.children.iter().zip(&visit.children_action_indices) {
+                if let Some(action_index) = action_index {
+                    if taken_by_chooser.contains(action_index) {
+                        child.record_amaf_result(chooser_reward);
+                    }
                 }
+            }
 
-                // The reward is only positive if the action taken was
-                // from the perspective of the self player.
-                let self_factor = if parent_was_self { 1 } else { -1 };
-                metadata.record_result(self_factor * reward);
+            if let Some(action_index) = visit.action_index {
+                taken[visit.mover].insert(action_index);
             }
+        }
+    }
 
-            self.number_iterations.fetch_add(1, Ordering::SeqCst);
+    /// Mark the terminal node at the end of `visited` as proven, then propagate
+    /// the proof up the visited nodes for as long as they can be proven.
+    fn propagate_proofs(&self, visited: &[Visit], rewards: &[f64]) {
+        let mut visits = visited.iter().rev();
+        let terminal = visits.next().expect("terminal node was visited");
+        terminal
+            .metadata
+            .set_proof(Proof::from_reward(rewards[terminal.mover]));
+
+        for visit in visits {
+            match visit.proof_from_children() {
+                Some(proof) => visit.metadata.set_proof(proof),
+                None => break,
+            }
         }
     }
 
-    fn load_metadata(node: &impl GameTreeNode, map: &MetadataMap) -> Arc<NodeMetadata> {
-        let hash = hash(node);
-        map.get_or_insert_with(&hash, || Arc::new(NodeMetadata::new()))
-            .clone()
+    /// Add the statistics of `root` and its children from `private_tree`,
+    /// searched by a single worker with `Parallelism::Root`, to this tree.
+    fn merge_root_statistics<N: GameTreeNode<Node = N>>(
+        &self,
+        private_tree: &SearchTree,
+        root: &N,
+    ) {
+        let mut nodes = vec![root.clone()];
+        match root.calculate_state() {
+            NodeState::HasChildren(children) => nodes.extend(children),
+            NodeState::Chance(outcomes) => {
+                nodes.extend(outcomes.into_iter().map(|(outcome, _weight)| outcome))
+            }
+            NodeState::Reward(_) => (),
+        }
+
+        for node in nodes {
+            if let Some(private_metadata) = private_tree.get_metadata(&node) {
+                self.load_metadata(&node).merge(&private_metadata);
+            }
+        }
+    }
+
+    fn revert_virtual_loss(&self, visited: &[Visit]) {
+        for visit in visited {
+            visit.metadata.remove_virtual_loss(self.config.virtual_loss);
+        }
+    }
+
+    fn load_metadata(&self, node: &impl GameTreeNode) -> Arc<NodeMetadata> {
+        self.load_metadata_by_hash(hash(node), || check_hash(node))
+    }
+
+    /// Load the metadata of the node with `node_hash`, where
+    /// `check_hash` is only called if the check hash is needed.
+    fn load_metadata_by_hash(
+        &self,
+        node_hash: u64,
+        check_hash: impl Fn() -> u64,
+    ) -> Arc<NodeMetadata> {
+        let verified_check_hash = if self.config.verify_hashes {
+            Some(check_hash())
+        } else {
+            None
+        };
+
+        if let Some(node_budget) = self.config.node_budget {
+            // Stored nodes can have a check hash even if this search doesn't verify
+            // hashes, such as nodes from an earlier search or a loaded tree.
+            let existing = self.tree.find_metadata(node_hash, || {
+                verified_check_hash.unwrap_or_else(&check_hash)
+            });
+            if let Some(metadata) = existing {
+                metadata.set_generation(self.generation);
+                return metadata;
+            }
+
+            let max_nodes = node_budget.max_nodes();
+            if self.tree.number_of_nodes() >= max_nodes {
+                let target = max_nodes - max_nodes / EVICTION_FRACTION;
+                self.tree
+                    .evict(target, self.config.eviction_policy, self.root_hash);
+
+                // If the tree is still full the search carries on with statistics
+                // which are not stored, rather than growing past the budget.
+                if self.tree.number_of_nodes() >= max_nodes {
+                    return Arc::new(NodeMetadata::new(verified_check_hash));
+                }
+            }
+        }
+
+        let metadata = self.tree.insert_metadata(node_hash, verified_check_hash);
+        metadata.set_generation(self.generation);
+        metadata
+    }
+}
+
+/// Run `task` once on each thread of `thread_pool`, or of rayon's global pool if `None`.
+fn run_on_each_thread(thread_pool: Option<&ThreadPool>, task: impl Fn() + Sync) {
+    let run = || {
+        let _: () = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .map(|_| task())
+            .collect();
+    };
+    match thread_pool {
+        Some(thread_pool) => thread_pool.install(run),
+        None => run(),
+    }
+}
+
+/// Whether the most visited of the children with `visits` would still be the most visited
+/// if every remaining iteration went to the child with the second most visits.
+pub(crate) fn leader_is_decided(
+    visits: impl IntoIterator<Item = u32>,
+    remaining_iterations: u64,
+) -> bool {
+    let mut number_of_children = 0;
+    let (mut most_visits, mut second_most_visits) = (0, 0);
+    for visits in visits {
+        number_of_children += 1;
+        let visits = u64::from(visits);
+        if visits > most_visits {
+            second_most_visits = most_visits;
+            most_visits = visits;
+        } else if visits > second_most_visits {
+            second_most_visits = visits;
+        }
+    }
+
+    number_of_children >= 2 && most_visits - second_most_visits > remaining_iterations
+}
+
+/// The weighted average of the mean rewards of the visited `outcomes`, `None` if none are visited.
+fn expected_reward(outcomes: &[Arc<NodeMetadata>], weights: &[f64]) -> Option<f64> {
+    let (mut total, mut total_weight) = (0.0, 0.0);
+    for (outcome, &weight) in outcomes.iter().zip(weights) {
+        let number_of_visits = outcome.number_of_visits();
+        if number_of_visits > 0 {
+            total += weight * outcome.total_reward() / f64::from(number_of_visits);
+            total_weight += weight;
+        }
+    }
+
+    if total_weight > 0.0 {
+        Some(total / total_weight)
+    } else {
+        None
+    }
+}
+
+/// The rewards of a two-player game where `player` is given `reward`.
+fn two_player_rewards(player: usize, reward: f64) -> Vec<f64> {
+    let mut rewards = vec![-reward; 2];
+    rewards[player] = reward;
+    rewards
+}
+
+/// Ranks proven wins before unproven nodes and draws, and proven losses last.
+fn proof_rank(proof: Option<Proof>) -> u8 {
+    match proof {
+        Some(Proof::Win) => 2,
+        Some(Proof::Draw) | None => 1,
+        Some(Proof::Loss) => 0,
     }
 }
 
 fn hash(node: &impl GameTreeNode) -> u64 {
-    let mut hasher = DefaultHasher::new();
+    let mut hasher = StableHasher::with_seed(HASH_SEED);
+    node.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A second hash of the node, independent from `hash`, used
+/// to detect when two different nodes share the same hash.
+fn check_hash(node: &impl GameTreeNode) -> u64 {
+    let mut hasher = StableHasher::with_seed(CHECK_HASH_SEED);
     node.hash(&mut hasher);
     hasher.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DirichletNoise, Evaluation, NodeBudget, ProgressiveWidening, Puct};
+    use game_tree::games::nim::NimState;
+    use game_tree::games::pig::{PigAction, PigState};
+    use game_tree::games::tic_tac_toe::{TicTacToeAction, TicTacToeState};
+    use game_tree::GameAction;
+    use rand::RngCore;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn children<N: GameTreeNode<Node = N>>(node: &N) -> Vec<N> {
+        match node.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            s => panic!("expected NodeState::HasChildren, got: {:?}", s),
+        }
+    }
+
+    fn config(max_iterations: u64) -> SearchConfig {
+        SearchConfig {
+            max_duration: Duration::from_secs(60),
+            max_iterations,
+            ..SearchConfig::default()
+        }
+    }
+
+    /// Values every leaf at 0.25 for the self player and gives
+    /// each child a prior proportional to its position.
+    struct FixedEvaluator;
+
+    impl<N: GameTreeNode> Evaluator<N> for FixedEvaluator {
+        fn evaluate(&self, _node: &N, children: &[N], _rand: &mut dyn RngCore) -> Evaluation {
+            let total = (children.len() * (children.len() + 1) / 2) as f64;
+            Evaluation {
+                values: vec![0.25, -0.25],
+                priors: (1..=children.len()).map(|i| i as f64 / total).collect(),
+                played_actions: vec![],
+            }
+        }
+    }
+
+    #[test]
+    fn search_backs_up_evaluations() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        // Too few iterations to reach a terminal node, so every value comes from the evaluator.
+        tree.search_with_evaluator(
+            root.clone(),
+            SearchConfig {
+                parallelism: Parallelism::SingleThreaded,
+                ..config(30)
+            },
+            &FixedEvaluator,
+        );
+
+        let analysis = tree.analyze(&root);
+        assert_eq!(analysis.len(), 9);
+        for analysis in analysis {
+            let expected_prior = (analysis.action.index() + 1) as f64 / 45.0;
+            assert!((analysis.prior - expected_prior).abs() < 1e-9);
+            // X moved to the children of the root.
+            assert!(analysis.number_of_visits > 0);
+            assert!((analysis.mean_value - 0.25).abs() < 1e-9);
+        }
+
+        // O is assumed to have moved to the root.
+        let root_metadata = tree.get_metadata(&root).unwrap();
+        let root_mean = root_metadata.total_reward() / f64::from(root_metadata.number_of_visits());
+        assert!((root_mean + 0.25).abs() < 1e-9);
+    }
+
+    thread_local! {
+        static CHILDREN_GENERATED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Tic-tac-toe which counts the children it generates on this thread, and
+    /// only generates them lazily if `lazy`.
+    #[derive(Debug, Clone, Hash)]
+    struct CountingTicTacToe {
+        state: TicTacToeState,
+        lazy: bool,
+    }
+
+    impl CountingTicTacToe {
+        fn generated(&self, state: TicTacToeState) -> Self {
+            CHILDREN_GENERATED.with(|generated| generated.set(generated.get() + 1));
+            CountingTicTacToe {
+                state,
+                lazy: self.lazy,
+            }
+        }
+    }
+
+    impl GameTreeNode for CountingTicTacToe {
+        type Node = CountingTicTacToe;
+        type Action = TicTacToeAction;
+
+        const ACTION_SPACE_SIZE: usize = TicTacToeState::ACTION_SPACE_SIZE;
+
+        fn current_player(&self) -> usize {
+            self.state.current_player()
+        }
+
+        fn calculate_state(&self) -> NodeState<Self::Node> {
+            match self.state.calculate_state() {
+                NodeState::HasChildren(children) => NodeState::HasChildren(
+                    children.into_iter().map(|c| self.generated(c)).collect(),
+                ),
+                NodeState::Reward(rewards) => NodeState::Reward(rewards),
+                NodeState::Chance(_) => unreachable!("tic-tac-toe has no chance nodes"),
+            }
+        }
+
+        fn legal_actions(&self) -> Vec<Self::Action> {
+            self.state.legal_actions()
+        }
+
+        fn apply(&self, action: &Self::Action) -> Self::Node {
+            self.generated(self.state.apply(action))
+        }
+
+        fn generates_children_lazily(&self) -> bool {
+            self.lazy
+        }
+
+        fn reward(&self) -> Option<Vec<i64>> {
+            self.state.reward()
+        }
+
+        fn number_of_children(&self) -> usize {
+            self.state.number_of_children()
+        }
+
+        fn child(&self, index: usize) -> Self::Node {
+            self.generated(self.state.child(index))
+        }
+    }
+
+    #[test]
+    fn search_only_generates_chosen_children_of_lazy_nodes() {
+        // The children generated by a search, without rollouts.
+        let children_generated = |lazy| {
+            CHILDREN_GENERATED.with(|generated| generated.set(0));
+            let root = CountingTicTacToe {
+                state: TicTacToeState::new(),
+                lazy,
+            };
+            SearchTree::new().search_with_evaluator(
+                root,
+                SearchConfig {
+                    seed: Some(0),
+                    ..config(2_000)
+                },
+                &FixedEvaluator,
+            );
+            CHILDREN_GENERATED.with(|generated| generated.get())
+        };
+
+        let lazy = children_generated(true);
+        let eager = children_generated(false);
+        assert!(lazy * 2 < eager);
+    }
+
+    /// Evaluates leaves with `RandomRollout`, recording the largest batch evaluated.
+    #[derive(Default)]
+    struct BatchCountingEvaluator {
+        largest_batch: AtomicUsize,
+    }
+
+    impl<N: GameTreeNode<Node = N>> Evaluator<N> for BatchCountingEvaluator {
+        fn evaluate(&self, node: &N, children: &[N], rand: &mut dyn RngCore) -> Evaluation {
+            RandomRollout::default().evaluate(node, children, rand)
+        }
+
+        fn evaluate_batch(
+            &self,
+            leaves: &[(N, Vec<N>)],
+            rand: &mut dyn RngCore,
+        ) -> Vec<Evaluation> {
+            self.largest_batch.fetch_max(leaves.len(), Ordering::SeqCst);
+            leaves
+                .iter()
+                .map(|(node, children)| self.evaluate(node, children, rand))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn batched_search_evaluates_leaves_together() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        let evaluator = BatchCountingEvaluator::default();
+        let stats = tree.search_with_evaluator(
+            root.clone(),
+            SearchConfig {
+                batch_size: 16,
+                parallelism: Parallelism::SingleThreaded,
+                ..config(2_000)
+            },
+            &evaluator,
+        );
+
+        let largest_batch = evaluator.largest_batch.load(Ordering::SeqCst);
+        assert!(largest_batch > 1 && largest_batch <= 16);
+        assert_eq!(
+            u64::from(tree.get_number_of_visits(&root)),
+            stats.number_of_iterations
+        );
+        assert!(
+            tree.node_metadata
+                .iter()
+                .all(|entry| !entry.value().has_virtual_loss()),
+            "virtual loss is removed once leaves are evaluated"
+        );
+    }
+
+    #[test]
+    fn advance_root_keeps_only_reachable_statistics() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(root.clone(), config(2_000));
+
+        let (_, child) = tree.select_most_visited_child(children(&root));
+        let grandchild = children(&child).remove(0);
+        let child_visits = tree.get_number_of_visits(&child);
+        let grandchild_visits = tree.get_number_of_visits(&grandchild);
+        let number_of_nodes = tree.number_of_nodes();
+
+        let dropped = tree.advance_root(&child);
+        assert!(dropped > 0, "expected sibling subtrees to be dropped");
+        assert_eq!(tree.number_of_nodes(), number_of_nodes - dropped);
+        assert_eq!(tree.get_number_of_visits(&root), 0);
+        assert_eq!(tree.get_number_of_visits(&child), child_visits);
+        assert_eq!(tree.get_number_of_visits(&grandchild), grandchild_visits);
+    }
+
+    #[test]
+    fn search_stays_within_node_budget() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        for &eviction_policy in &[
+            EvictionPolicy::LeastVisited,
+            EvictionPolicy::OldestGeneration,
+        ] {
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    node_budget: Some(NodeBudget::Count(200)),
+                    eviction_policy,
+                    ..config(5_000)
+                },
+            );
+
+            // Concurrent tasks can each insert a node before seeing the budget is reached.
+            assert!(tree.number_of_nodes() <= 200 + rayon::current_num_threads());
+            assert!(
+                tree.get_number_of_visits(&root) > 0,
+                "root is never evicted"
+            );
+        }
+    }
+
+    #[test]
+    fn colliding_nodes_do_not_share_statistics() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+
+        // Simulate a different node which was stored under the same hash as the root.
+        let colliding = tree.insert_metadata(hash(&root), Some(check_hash(&root) ^ 1));
+        colliding.record_result(1.0);
+
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                verify_hashes: true,
+                ..config(100)
+            },
+        );
+
+        assert_eq!(tree.number_of_collisions(), 1);
+        assert_eq!(colliding.number_of_visits(), 1);
+        assert!(tree.get_number_of_visits(&root) > 1);
+    }
+
+    #[test]
+    fn root_noise_changes_the_child_visited() {
+        let root = TicTacToeState::new();
+        // The child of the root chosen by the first descent of a seeded PUCT search.
+        let visited_children = |root_noise| {
+            let tree = SearchTree::new();
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    selection: Arc::new(Puct),
+                    root_noise,
+                    seed: Some(0),
+                    ..config(1)
+                },
+            );
+            children(&root)
+                .iter()
+                .map(|child| tree.get_number_of_visits(child) > 0)
+                .collect::<Vec<_>>()
+        };
+
+        // Without noise every child has the same prior.
+        let without_noise = visited_children(None);
+        assert_eq!(without_noise.iter().filter(|&&visited| visited).count(), 1);
+        assert_eq!(
+            visited_children(Some(DirichletNoise::new(0.3, 0.0))),
+            without_noise
+        );
+        assert_ne!(
+            visited_children(Some(DirichletNoise::new(0.3, 0.25))),
+            without_noise
+        );
+    }
+
+    #[test]
+    fn colliding_nodes_count_towards_node_budget() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                verify_hashes: true,
+                ..config(200)
+            },
+        );
+
+        // Simulate a different node stored under the same hash as each child.
+        let number_of_nodes = tree.number_of_nodes();
+        for child in children(&root) {
+            tree.insert_metadata(hash(&child), Some(check_hash(&child) ^ 1));
+        }
+        assert_eq!(tree.number_of_nodes(), number_of_nodes + 9);
+
+        tree.evict(5, EvictionPolicy::LeastVisited, hash(&root));
+        assert_eq!(tree.number_of_nodes(), 5);
+        assert_eq!(
+            tree.node_metadata.len() + tree.colliding_node_metadata.len(),
+            5
+        );
+    }
+
+    #[test]
+    fn node_budget_finds_nodes_stored_with_check_hashes() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                verify_hashes: true,
+                ..config(100)
+            },
+        );
+        let root_visits = tree.get_number_of_visits(&root);
+
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                node_budget: Some(NodeBudget::Count(10_000)),
+                ..config(100)
+            },
+        );
+        assert!(tree.get_number_of_visits(&root) > root_visits);
+    }
+
+    #[test]
+    fn solver_proves_forced_win() {
+        // X in the corner and O on an adjacent edge is a forced win for X.
+        let root = children(&children(&TicTacToeState::new())[0])[0].clone();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                solver: true,
+                ..config(1_000_000)
+            },
+        );
+
+        assert_eq!(
+            tree.get_proof(&root),
+            Some(Proof::Loss),
+            "O moved into a loss"
+        );
+        assert!(
+            tree.get_number_of_visits(&root) < 1_000_000,
+            "search stops once the root is solved"
+        );
+
+        let (_, child) = tree.select_most_visited_child(children(&root));
+        assert_eq!(tree.get_proof(&child), Some(Proof::Win));
+    }
+
+    #[test]
+    fn solver_solves_tic_tac_toe() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        let stats = tree.search(
+            root.clone(),
+            SearchConfig {
+                solver: true,
+                ..config(1_000_000)
+            },
+        );
+
+        assert_eq!(tree.get_proof(&root), Some(Proof::Draw));
+        assert!(
+            stats.number_of_iterations < 1_000_000,
+            "search stops once the root is solved"
+        );
+        for child in children(&root) {
+            assert!(tree.get_proof(&child).is_some(), "{:?} is proven", child);
+        }
+    }
+
+    #[test]
+    fn analyze_orders_moves_by_preference() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(root.clone(), config(500));
+
+        let analysis = tree.analyze(&root);
+        assert_eq!(analysis.len(), 9);
+        assert_eq!(
+            analysis[0].number_of_visits,
+            tree.select_most_visited_child(children(&root)).0
+        );
+        assert!(analysis
+            .windows(2)
+            .all(|pair| pair[0].number_of_visits >= pair[1].number_of_visits));
+
+        let best = &analysis[0];
+        assert_eq!(root.apply(&best.action), best.node);
+        assert!(!best.principal_variation.is_empty());
+        assert!(children(&best.node).contains(&best.principal_variation[0]));
+    }
+
+    #[test]
+    fn select_child_with_temperature_samples_visited_children() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(root.clone(), config(500));
+
+        let distribution = tree.visit_distribution(&children(&root));
+        assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        let mut rand = StdRng::seed_from_u64(0);
+        let (_, most_visited) = tree.select_most_visited_child(children(&root));
+        let greedy = tree.select_child_with_temperature(children(&root), 0.0, &mut rand);
+        assert_eq!(greedy, most_visited);
+
+        let sampled = tree.select_child_with_temperature(children(&root), 1.0, &mut rand);
+        assert!(tree.get_number_of_visits(&sampled) > 0);
+    }
+
+    #[test]
+    fn pondering_continues_until_stopped() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+
+        let handle = tree.ponder(root.clone(), config(1));
+        while handle.number_of_iterations() < 100 {
+            std::thread::yield_now();
+        }
+        assert!(!handle.is_finished());
+        handle.stop();
+
+        let number_of_visits = tree.get_number_of_visits(&root);
+        assert!(number_of_visits > 100);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(tree.get_number_of_visits(&root), number_of_visits);
+    }
+
+    #[test]
+    fn started_search_finishes_at_its_limits() {
+        let tree = SearchTree::new();
+        let handle = tree.start_search(TicTacToeState::new(), config(200));
+        handle.wait();
+        assert!(tree.get_number_of_visits(&TicTacToeState::new()) >= 200);
+    }
+
+    #[test]
+    fn smart_stop_ends_decided_searches() {
+        let root = children(&children(&TicTacToeState::new())[0])[0].clone();
+        let tree = SearchTree::new();
+        let stats = tree.search(
+            root,
+            SearchConfig {
+                smart_stop: true,
+                seed: Some(0),
+                ..config(10_000)
+            },
+        );
+
+        assert!(stats.iterations_saved > 0);
+        assert_eq!(stats.number_of_iterations + stats.iterations_saved, 10_000);
+    }
+
+    #[test]
+    fn root_parallel_search_ignores_smart_stop() {
+        let root = children(&children(&TicTacToeState::new())[0])[0].clone();
+        let tree = SearchTree::new();
+        let stats = tree.search(
+            root,
+            SearchConfig {
+                smart_stop: true,
+                parallelism: Parallelism::Root,
+                number_of_threads: Some(2),
+                ..config(10_000)
+            },
+        );
+
+        assert_eq!(stats.iterations_saved, 0);
+        assert!(stats.number_of_iterations >= 10_000);
+    }
+
+    #[test]
+    fn single_threaded_search_runs_every_iteration() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        let stats = tree.search(
+            root.clone(),
+            SearchConfig {
+                parallelism: Parallelism::SingleThreaded,
+                ..config(300)
+            },
+        );
+
+        assert!(stats.number_of_iterations >= 300);
+        assert_eq!(
+            u64::from(tree.get_number_of_visits(&root)),
+            stats.number_of_iterations
+        );
+    }
+
+    /// Evaluates leaves by random rollouts, recording the size of the largest
+    /// thread pool the leaves were evaluated on.
+    #[derive(Default)]
+    struct PoolSizeEvaluator {
+        largest_pool: AtomicUsize,
+    }
+
+    impl<N: GameTreeNode<Node = N>> Evaluator<N> for PoolSizeEvaluator {
+        fn evaluate(&self, node: &N, children: &[N], rand: &mut dyn RngCore) -> Evaluation {
+            self.largest_pool
+                .fetch_max(rayon::current_num_threads(), Ordering::SeqCst);
+            RandomRollout::default().evaluate(node, children, rand)
+        }
+    }
+
+    #[test]
+    fn parallel_search_runs_on_number_of_threads() {
+        for &parallelism in &[Parallelism::Tree, Parallelism::Root] {
+            let evaluator = PoolSizeEvaluator::default();
+            SearchTree::new().search_with_evaluator(
+                TicTacToeState::new(),
+                SearchConfig {
+                    parallelism,
+                    number_of_threads: Some(3),
+                    ..config(300)
+                },
+                &evaluator,
+            );
+            assert_eq!(evaluator.largest_pool.load(Ordering::SeqCst), 3);
+        }
+    }
+
+    #[test]
+    fn thread_pool_is_reused_between_searches() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        let search = |number_of_threads| {
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    number_of_threads: Some(number_of_threads),
+                    ..config(100)
+                },
+            );
+            let thread_pool = tree.thread_pool.lock().unwrap();
+            Arc::clone(thread_pool.as_ref().expect("thread pool was built"))
+        };
+
+        let thread_pool = search(2);
+        assert!(Arc::ptr_eq(&thread_pool, &search(2)));
+        assert_eq!(search(3).current_num_threads(), 3);
+    }
+
+    #[test]
+    fn merged_root_statistics_can_be_searched_again() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                parallelism: Parallelism::Root,
+                number_of_threads: Some(2),
+                ..config(300)
+            },
+        );
+
+        // The children of the root are expanded again, since their subtrees weren't merged.
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                selection: Arc::new(Puct),
+                seed: Some(0),
+                ..config(300)
+            },
+        );
+        for child in children(&root) {
+            for grandchild in children(&child) {
+                if let Some(metadata) = tree.get_metadata(&grandchild) {
+                    assert!(metadata.prior() > 0.0, "grandchildren have priors");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn root_parallel_search_merges_root_statistics() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        let stats = tree.search(
+            root.clone(),
+            SearchConfig {
+                parallelism: Parallelism::Root,
+                number_of_threads: Some(2),
+                ..config(300)
+            },
+        );
+
+        // Only the root and its children are kept from the private trees.
+        assert_eq!(tree.number_of_nodes(), 10);
+        assert_eq!(
+            u64::from(tree.get_number_of_visits(&root)),
+            stats.number_of_iterations
+        );
+    }
+
+    #[test]
+    fn rave_records_all_moves_as_first_statistics() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                rave_equivalence: Some(100.0),
+                ..config(500)
+            },
+        );
+
+        for child in children(&root) {
+            let metadata = tree.get_metadata(&child).expect("child was searched");
+            // Every simulation takes most of the actions at some point.
+            assert!(metadata.amaf_visits() > metadata.number_of_visits());
+        }
+    }
+
+    #[test]
+    fn random_rollout_only_records_actions_when_asked() {
+        let root = TicTacToeState::new();
+        let children = children(&root);
+        let played_actions = |record_actions| {
+            let mut rand = StdRng::seed_from_u64(0);
+            RandomRollout { record_actions }
+                .evaluate(&root, &children, &mut rand)
+                .played_actions
+        };
+
+        assert!(played_actions(false).is_empty());
+        // At least five moves are played before a line is completed.
+        assert!(played_actions(true).len() >= 5);
+    }
+
+    #[test]
+    fn solver_widens_into_unproven_children() {
+        // X can win with the last move, 8.
+        let root = [0, 1, 4, 2]
+            .iter()
+            .fold(TicTacToeState::new(), |node, &slot| {
+                node.apply(&TicTacToeAction::new(slot))
+            });
+        let children = children(&root);
+        let tree = SearchTree::new();
+        // The first child is already proven to lose for X.
+        tree.insert_metadata(hash(&children[0]), None)
+            .set_proof(Proof::Loss);
+
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                solver: true,
+                // Only a single child is considered at a time.
+                progressive_widening: Some(ProgressiveWidening { k: 1.0, alpha: 0.0 }),
+                ..config(10_000)
+            },
+        );
+
+        assert_eq!(tree.get_number_of_visits(&children[0]), 0);
+        assert_eq!(
+            tree.get_proof(&root),
+            Some(Proof::Loss),
+            "O moved into a loss"
+        );
+    }
+
+    #[test]
+    fn progressive_widening_limits_children_considered() {
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(
+            root.clone(),
+            SearchConfig {
+                progressive_widening: Some(ProgressiveWidening {
+                    k: 1.0,
+                    alpha: 0.25,
+                }),
+                ..config(100)
+            },
+        );
+
+        // At most ceil(101^0.25) = 4 children after 100 iterations.
+        let visited_children = children(&root)
+            .iter()
+            .filter(|child| tree.get_number_of_visits(*child) > 0)
+            .count();
+        assert!(visited_children > 1 && visited_children <= 4);
+    }
+
+    #[test]
+    fn seeded_searches_are_identical() {
+        let root = TicTacToeState::new();
+        let search = |seed| {
+            let tree = SearchTree::new();
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    seed: Some(seed),
+                    ..config(2_000)
+                },
+            );
+
+            children(&root)
+                .iter()
+                .flat_map(|child| {
+                    let mut visits = vec![tree.get_number_of_visits(child)];
+                    visits.extend(children(child).iter().map(|c| tree.get_number_of_visits(c)));
+                    visits
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search(7), search(7));
+        assert_ne!(search(7), search(8));
+    }
+
+    #[test]
+    fn multi_player_search_takes_the_winning_move() {
+        let root = NimState::new(vec![3], 3);
+        for &backup in &[Backup::MaxN, Backup::Paranoid] {
+            let tree = SearchTree::new();
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    backup,
+                    // Ignored with more than two players.
+                    solver: true,
+                    ..config(300)
+                },
+            );
+
+            let (_, child) = tree.select_most_visited_child(children(&root));
+            assert_eq!(child.reward(), Some(vec![1, -1, -1]), "{:?}", backup);
+            assert_eq!(tree.get_proof(&child), None);
+        }
+    }
+
+    #[test]
+    fn chance_nodes_sample_their_outcomes() {
+        // After rolling a 6, rolling again wins unless the die lands on a 1.
+        let rolling = PigState::new(8).apply(&PigAction::Roll);
+        let root = match rolling.calculate_state() {
+            NodeState::Chance(mut outcomes) => outcomes.swap_remove(5).0,
+            s => panic!("expected NodeState::Chance, got: {:?}", s),
+        };
+
+        for &chance_backup in &[ChanceBackup::Sampled, ChanceBackup::Expectation] {
+            let tree = SearchTree::new();
+            tree.search(
+                root.clone(),
+                SearchConfig {
+                    chance_backup,
+                    solver: true,
+                    ..config(1_000)
+                },
+            );
+
+            let (_, child) = tree.select_most_visited_child(children(&root));
+            assert_eq!(child, root.apply(&PigAction::Roll), "{:?}", chance_backup);
+
+            // Every roll but a 1 wins straight away, rolling again is
+            // only proven to win once rolling a 1 is too.
+            let rolling_again = root.apply(&PigAction::Roll);
+            let outcomes = match rolling_again.calculate_state() {
+                NodeState::Chance(outcomes) => outcomes,
+                s => panic!("expected NodeState::Chance, got: {:?}", s),
+            };
+            for (outcome, _) in &outcomes[1..] {
+                assert_eq!(tree.get_proof(outcome), Some(Proof::Win));
+            }
+            if tree.get_proof(&outcomes[0].0) != Some(Proof::Win) {
+                assert_ne!(
+                    tree.get_proof(&rolling_again),
+                    Some(Proof::Win),
+                    "{:?}",
+                    chance_backup
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chance_nodes_back_up_the_expectation_of_their_outcomes() {
+        let rolling = PigState::new(8).apply(&PigAction::Roll);
+        let (outcomes, weights): (Vec<_>, Vec<_>) = match rolling.calculate_state() {
+            NodeState::Chance(outcomes) => outcomes.into_iter().unzip(),
+            s => panic!("expected NodeState::Chance, got: {:?}", s),
+        };
+        let tree = SearchTree::new();
+        let search = |max_iterations| {
+            tree.search(
+                rolling.clone(),
+                SearchConfig {
+                    chance_backup: ChanceBackup::Expectation,
+                    seed: Some(0),
+                    ..config(max_iterations)
+                },
+            );
+        };
+
+        search(500);
+        let before = tree.get_metadata(&rolling).unwrap();
+        let (visits, total_reward) = (before.number_of_visits(), before.total_reward());
+        // The search stops once more than `max_iterations` are completed.
+        search(0);
+        let after = tree.get_metadata(&rolling).unwrap();
+        assert_eq!(after.number_of_visits(), visits + 1);
+        let recorded = after.total_reward() - total_reward;
+
+        let mut weighted_means = 0.0;
+        for (outcome, weight) in outcomes.iter().zip(&weights) {
+            let metadata = tree.get_metadata(outcome).expect("outcome was searched");
+            weighted_means +=
+                weight * metadata.total_reward() / f64::from(metadata.number_of_visits());
+        }
+        let expectation = weighted_means / weights.iter().sum::<f64>();
+        assert!(
+            (recorded - expectation).abs() < 1e-9,
+            "recorded {}, expected {}",
+            recorded,
+            expectation
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use rand::RngCore;
+use rand_distr::{Beta, Distribution};
+use std::fmt::Debug;
+
+/// The statistics of a node used to decide which child to descend into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelectionStats {
+    /// The number of visits, including descents in progress.
+    pub number_of_visits: f64,
+    /// The mean reward from the perspective of the player choosing the node,
+    /// including the virtual loss of descents in progress and any RAVE
+    /// statistics. `None` if there are no statistics for the node yet.
+    pub mean_reward: Option<f64>,
+    /// The variance of the rewards recorded, 0 until the node is visited.
+    pub reward_variance: f64,
+    /// The prior probability of choosing the node, including any noise at the root.
+    pub prior: f64,
+}
+
+/// The formula used to choose which child to descend into, the child
+/// with the highest score is chosen.
+///
+/// Rewards are assumed to be between -1 and 1.
+pub trait SelectionPolicy: Debug + Send + Sync {
+    /// The score of `child` when choosing between the children of `parent`,
+    /// `exploration_factor` scales how much exploration is preferred.
+    fn score(
+        &self,
+        parent: &SelectionStats,
+        child: &SelectionStats,
+        exploration_factor: f64,
+        rand: &mut dyn RngCore,
+    ) -> f64;
+
+    /// Whether every child of a node is visited (in a random order) before
+    /// `score` is used to choose between them.
+    fn visits_every_child_first(&self) -> bool {
+        true
+    }
+}
+
+/// UCB1 (as used by UCT), `Q + c * sqrt(ln(N_parent) / N_child)`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ucb1;
+
+impl SelectionPolicy for Ucb1 {
+    fn score(
+        &self,
+        parent: &SelectionStats,
+        child: &SelectionStats,
+        exploration_factor: f64,
+        _rand: &mut dyn RngCore,
+    ) -> f64 {
+        let mean_reward = match child.mean_reward {
+            Some(mean_reward) if child.number_of_visits > 0.0 => mean_reward,
+            // Only possible when the node was evicted after its parent was fully expanded.
+            _ => return f64::INFINITY,
+        };
+
+        mean_reward
+            + exploration_factor * (parent.number_of_visits.ln() / child.number_of_visits).sqrt()
+    }
+}
+
+/// UCB1-Tuned, which scales the exploration of UCB1 by an upper
+/// bound on the variance of the rewards of the child.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ucb1Tuned;
+
+impl SelectionPolicy for Ucb1Tuned {
+    fn score(
+        &self,
+        parent: &SelectionStats,
+        child: &SelectionStats,
+        exploration_factor: f64,
+        _rand: &mut dyn RngCore,
+    ) -> f64 {
+        let mean_reward = match child.mean_reward {
+            Some(mean_reward) if child.number_of_visits > 0.0 => mean_reward,
+            _ => return f64::INFINITY,
+        };
+
+        let log_parent_visits = parent.number_of_visits.ln();
+        let variance_bound =
+            child.reward_variance + (2.0 * log_parent_visits / child.number_of_visits).sqrt();
+        // The variance of rewards between -1 and 1 is at most 1.
+        let exploration =
+            (log_parent_visits / child.number_of_visits * variance_bound.min(1.0)).sqrt();
+
+        mean_reward + exploration_factor * exploration
+    }
+}
+
+/// UCB-V, an upper confidence bound using the empirical variance of the rewards,
+/// `Q + sqrt(2 * V * ln(N_parent) / N_child) + c * 3 * b * ln(N_parent) / N_child`
+/// where `b` is the range of the rewards.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UcbV;
+
+impl SelectionPolicy for UcbV {
+    fn score(
+        &self,
+        parent: &SelectionStats,
+        child: &SelectionStats,
+        exploration_factor: f64,
+        _rand: &mut dyn RngCore,
+    ) -> f64 {
+        let mean_reward = match child.mean_reward {
+            Some(mean_reward) if child.number_of_visits > 0.0 => mean_reward,
+            _ => return f64::INFINITY,
+        };
+
+        let reward_range = 2.0;
+        let log_parent_visits = parent.number_of_visits.ln();
+        mean_reward
+            + (2.0 * child.reward_variance * log_parent_visits / child.number_of_visits).sqrt()
+            + exploration_factor * 3.0 * reward_range * log_parent_visits / child.number_of_visits
+    }
+}
+
+/// AlphaZero-style PUCT, `Q + c_puct * P * sqrt(N_parent) / (1 + N_child)` where
+/// `P` is the prior probability of the child and `c_puct` the exploration factor.
+///
+/// Children don't need to be visited first, unvisited children have a `Q` of 0.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Puct;
+
+impl SelectionPolicy for Puct {
+    fn score(
+        &self,
+        parent: &SelectionStats,
+        child: &SelectionStats,
+        exploration_factor: f64,
+        _rand: &mut dyn RngCore,
+    ) -> f64 {
+        child.mean_reward.unwrap_or(0.0)
+            + exploration_factor * child.prior * parent.number_of_visits.sqrt()
+                / (1.0 + child.number_of_visits)
+    }
+
+    fn visits_every_child_first(&self) -> bool {
+        false
+    }
+}
+
+/// Thompson sampling, the score of a child is sampled from a Beta distribution
+/// of its mean reward, treating rewards as fractions of a win.
+///
+/// The exploration factor is not used.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thompson;
+
+impl SelectionPolicy for Thompson {
+    fn score(
+        &self,
+        _parent: &SelectionStats,
+        child: &SelectionStats,
+        _exploration_factor: f64,
+        rand: &mut dyn RngCore,
+    ) -> f64 {
+        let mean_reward = child.mean_reward.unwrap_or(0.0);
+        let wins = (child.number_of_visits * (1.0 + mean_reward) / 2.0).max(0.0);
+        let losses = (child.number_of_visits - wins).max(0.0);
+
+        let beta = Beta::new(1.0 + wins, 1.0 + losses).expect("parameters are positive");
+        // From a fraction of a win to a reward between -1 and 1.
+        2.0 * beta.sample(rand) - 1.0
+    }
+
+    fn visits_every_child_first(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn stats(number_of_visits: f64, mean_reward: Option<f64>, prior: f64) -> SelectionStats {
+        SelectionStats {
+            number_of_visits,
+            mean_reward,
+            reward_variance: 0.0,
+            prior,
+        }
+    }
+
+    fn score(policy: impl SelectionPolicy, parent: SelectionStats, child: SelectionStats) -> f64 {
+        policy.score(&parent, &child, 1.0, &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn ucb1_uses_the_natural_log() {
+        let parent = stats(std::f64::consts::E, Some(0.0), 1.0);
+        let child = stats(1.0, Some(0.5), 1.0);
+        assert!((score(Ucb1, parent, child) - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ucb1_tuned_explores_less_with_low_variance() {
+        let parent = stats(100.0, Some(0.0), 1.0);
+        let child = stats(10.0, Some(0.0), 1.0);
+        assert!(score(Ucb1Tuned, parent, child) < score(Ucb1, parent, child));
+    }
+
+    #[test]
+    fn ucb_v_explores_more_with_high_variance() {
+        let parent = stats(100.0, Some(0.0), 1.0);
+        let low = stats(10.0, Some(0.0), 1.0);
+        let high = SelectionStats {
+            reward_variance: 1.0,
+            ..low
+        };
+        assert!(score(UcbV, parent, high) > score(UcbV, parent, low));
+    }
+
+    #[test]
+    fn puct_prefers_higher_prior_for_unvisited_nodes() {
+        let parent = stats(1.0, Some(0.0), 1.0);
+        let likely = stats(0.0, None, 0.9);
+        let unlikely = stats(0.0, None, 0.1);
+        assert!(score(Puct, parent, likely) > score(Puct, parent, unlikely));
+    }
+
+    #[test]
+    fn puct_exploration_decays_with_visits() {
+        let parent = stats(10.0, Some(0.0), 1.0);
+        let unvisited_score = score(Puct, parent, stats(0.0, None, 0.5));
+        let visited_score = score(Puct, parent, stats(1.0, Some(0.0), 0.5));
+
+        assert!((unvisited_score - 0.5 * 10.0_f64.sqrt()).abs() < 1e-9);
+        assert!((visited_score - 0.5 * 10.0_f64.sqrt() / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thompson_samples_rewards() {
+        let parent = stats(100.0, Some(0.0), 1.0);
+        let mut rand = StdRng::seed_from_u64(0);
+        let winning = (0..100)
+            .map(|_| Thompson.score(&parent, &stats(50.0, Some(0.8), 1.0), 1.0, &mut rand))
+            .sum::<f64>();
+        let losing = (0..100)
+            .map(|_| Thompson.score(&parent, &stats(50.0, Some(-0.8), 1.0), 1.0, &mut rand))
+            .sum::<f64>();
+        assert!(winning > losing);
+    }
+}
@@ -0,0 +1,68 @@
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A hasher whose output only depends on the seed and the bytes written,
+/// unlike `DefaultHasher` whose algorithm may change between Rust releases.
+///
+/// FNV-1a over the written bytes, with a final mixing step so
+/// that all bits of the output depend on the input.
+///
+/// Note that `Hash` implementations may write platform dependent bytes
+/// (such as `usize` in native endianness), so hashes are only stable
+/// between machines of the same pointer width and endianness.
+#[derive(Debug, Clone)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub fn with_seed(seed: u64) -> Self {
+        let mut hasher = Self {
+            state: FNV_OFFSET_BASIS,
+        };
+        hasher.write_u64(seed);
+        hasher
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        // Finalizer from SplitMix64.
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::Hash;
+
+    fn stable_hash(value: impl Hash, seed: u64) -> u64 {
+        let mut hasher = StableHasher::with_seed(seed);
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        // If this changes, any statistics stored by hash are invalidated.
+        assert_eq!(stable_hash(42u32, 0), 0x2bda_f47f_2a95_60cb);
+    }
+
+    #[test]
+    fn seeds_give_different_hashes() {
+        assert_ne!(stable_hash(42u32, 0), stable_hash(42u32, 1));
+    }
+}
@@ -0,0 +1,94 @@
+/// The temperature used to sample moves at each ply of a game,
+/// sampling in proportion to visit counts for the first
+/// `greedy_after_ply` plies and then always choosing the most visited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureSchedule {
+    pub temperature: f64,
+    pub greedy_after_ply: u32,
+}
+
+impl TemperatureSchedule {
+    pub fn temperature(&self, ply: u32) -> f64 {
+        if ply < self.greedy_after_ply {
+            self.temperature
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for TemperatureSchedule {
+    /// Like AlphaZero, a temperature of 1 for the opening moves.
+    fn default() -> Self {
+        Self {
+            temperature: 1.0,
+            greedy_after_ply: 30,
+        }
+    }
+}
+
+/// Convert visit counts into probabilities proportional to `visits^(1 / temperature)`,
+/// a temperature of 0 puts all the probability on the most visited.
+pub(crate) fn apply_temperature(visits: &[u32], temperature: f64) -> Vec<f64> {
+    let max_visits = visits.iter().cloned().max().unwrap_or(0);
+    if max_visits == 0 {
+        return vec![1.0 / visits.len() as f64; visits.len()];
+    }
+
+    if temperature <= 0.0 {
+        let number_of_max = visits.iter().filter(|&&v| v == max_visits).count() as f64;
+        return visits
+            .iter()
+            .map(|&v| {
+                if v == max_visits {
+                    1.0 / number_of_max
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+    }
+
+    // Relative to the most visited child so that low temperatures don't overflow.
+    let max_visits = f64::from(max_visits);
+    let weights = visits
+        .iter()
+        .map(|&v| ((f64::from(v) / max_visits).ln() / temperature).exp())
+        .collect::<Vec<_>>();
+    let total = weights.iter().sum::<f64>();
+    weights.into_iter().map(|weight| weight / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_of_one_is_proportional_to_visits() {
+        let probabilities = apply_temperature(&[1, 3, 0], 1.0);
+        assert_eq!(probabilities, vec![0.25, 0.75, 0.0]);
+    }
+
+    #[test]
+    fn temperature_of_zero_is_greedy() {
+        assert_eq!(apply_temperature(&[1, 3, 0], 0.0), vec![0.0, 1.0, 0.0]);
+        assert_eq!(apply_temperature(&[3, 3], 0.0), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn low_temperatures_do_not_overflow() {
+        let probabilities = apply_temperature(&[10_000, 9_000], 0.01);
+        assert!(probabilities[0] > 0.99);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn schedule_becomes_greedy() {
+        let schedule = TemperatureSchedule {
+            temperature: 1.0,
+            greedy_after_ply: 2,
+        };
+        assert_eq!(schedule.temperature(1), 1.0);
+        assert_eq!(schedule.temperature(2), 0.0);
+    }
+}
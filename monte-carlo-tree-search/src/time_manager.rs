@@ -0,0 +1,265 @@
+use game_tree::{GameTreeNode, NodeState};
+use std::cmp::Reverse;
+use std::thread;
+use std::time::Duration;
+
+use crate::search_tree::leader_is_decided;
+use crate::{SearchConfig, SearchTree};
+
+/// How often a search is checked to see if it should be stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The clock of a player in a timed game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    /// The time left on the clock.
+    pub remaining: Duration,
+    /// The time added to the clock after every move.
+    pub increment: Duration,
+    /// The number of moves to be made with the remaining time, `None`
+    /// if the remaining time must last for the rest of the game.
+    pub moves_to_go: Option<u32>,
+}
+
+/// The time a single move is allowed to take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBudget {
+    /// The time usually spent on the move.
+    pub target: Duration,
+    /// The time the move may be extended to when the best move is unstable.
+    pub maximum: Duration,
+}
+
+/// Divides the time on a `TimeControl` between moves, searching each move for
+/// roughly its share of the clock.
+///
+/// Searches are extended while the most visited child keeps changing, and
+/// stopped early once it can no longer be overtaken in the time left.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    control: TimeControl,
+
+    /// The number of moves the remaining time is divided between
+    /// when the time control has no `moves_to_go`.
+    pub expected_moves_to_go: u32,
+    /// How many times the target a move may be extended to.
+    pub maximum_extension: u32,
+    /// Kept in reserve on the clock for the time taken outside of the search.
+    pub overhead: Duration,
+}
+
+impl TimeManager {
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+
+            expected_moves_to_go: 30,
+            maximum_extension: 3,
+            overhead: Duration::from_millis(50),
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    /// Replace the clock, for example when a new game starts.
+    pub fn set_control(&mut self, control: TimeControl) {
+        self.control = control;
+    }
+
+    /// The time the next move is allowed to take.
+    pub fn allocate(&self) -> TimeBudget {
+        let usable = self.control.remaining.saturating_sub(self.overhead);
+        let moves_to_go = self
+            .control
+            .moves_to_go
+            .unwrap_or(self.expected_moves_to_go)
+            .max(1);
+
+        let target = (usable / moves_to_go + self.control.increment).min(usable);
+        let maximum = (target * self.maximum_extension).min(usable);
+        TimeBudget { target, maximum }
+    }
+
+    /// Update the clock after a move which took `elapsed`.
+    pub fn record_move(&mut self, elapsed: Duration) {
+        self.control.remaining =
+            self.control.remaining.saturating_sub(elapsed) + self.control.increment;
+        if let Some(moves_to_go) = &mut self.control.moves_to_go {
+            // Stays at 1 until the clock is replaced with `set_control`.
+            *moves_to_go = moves_to_go.saturating_sub(1).max(1);
+        }
+    }
+
+    /// Search from `node` within the time allocated to the next move, then record the
+    /// time taken on the clock. `config.max_duration` is replaced by the allocated time,
+    /// the search still stops early if `config.max_iterations` is reached.
+    ///
+    /// Returns the time taken.
+    pub fn search<N: GameTreeNode<Node = N> + 'static>(
+        &mut self,
+        tree: &SearchTree,
+        node: N,
+        config: SearchConfig,
+    ) -> Duration {
+        let children = match node.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            // There is nothing to choose between.
+            NodeState::Reward(_) | NodeState::Chance(_) => return Duration::from_secs(0),
+        };
+
+        let budget = self.allocate();
+        let handle = tree.start_search(
+            node,
+            SearchConfig {
+                max_duration: budget.maximum,
+                ..config
+            },
+        );
+
+        let mut leader = None;
+        // When the most visited child last changed.
+        let mut leader_changed_at = Duration::from_secs(0);
+        while !handle.is_finished() {
+            thread::sleep(POLL_INTERVAL.min(budget.target / 10));
+            let elapsed = handle.elapsed();
+
+            // Total visits, including those from earlier searches of the same tree,
+            // since they decide which child is chosen once the search ends.
+            let visits = children
+                .iter()
+                .map(|child| tree.get_number_of_visits(child))
+                .collect::<Vec<_>>();
+            let current_leader = visits
+                .iter()
+                .enumerate()
+                .max_by_key(|&(i, &visits)| (visits, Reverse(i)))
+                .map(|(i, _visits)| i);
+            if leader != current_leader {
+                leader = current_leader;
+                leader_changed_at = elapsed;
+            }
+
+            // A best move which changed in the last quarter of the
+            // target is unstable, so keep searching up to the maximum.
+            let unstable = elapsed.saturating_sub(leader_changed_at) < budget.target / 4;
+            let limit = if unstable {
+                budget.maximum
+            } else {
+                budget.target
+            };
+            if elapsed >= limit {
+                break;
+            }
+
+            // Assuming iterations continue at the same rate, every remaining
+            // iteration going to the runner up is not enough to overtake the leader.
+            let iterations_per_second =
+                handle.number_of_iterations() as f64 / elapsed.as_secs_f64().max(1e-9);
+            let remaining_iterations = iterations_per_second * (limit - elapsed).as_secs_f64();
+            if leader_is_decided(visits, remaining_iterations as u64) {
+                break;
+            }
+        }
+
+        let elapsed = handle.elapsed();
+        handle.stop();
+        self.record_move(elapsed);
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> TimeManager {
+        let mut manager = TimeManager::new(TimeControl {
+            remaining: Duration::from_millis(remaining_ms),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+        });
+        manager.overhead = Duration::from_millis(0);
+        manager
+    }
+
+    #[test]
+    fn allocates_a_share_of_the_remaining_time() {
+        let budget = manager(10_000, 100, Some(10)).allocate();
+        assert_eq!(budget.target, Duration::from_millis(1_100));
+        assert_eq!(budget.maximum, Duration::from_millis(3_300));
+
+        // Never more than is left on the clock.
+        let budget = manager(1_000, 500, Some(1)).allocate();
+        assert_eq!(budget.target, Duration::from_millis(1_000));
+        assert_eq!(budget.maximum, Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn record_move_updates_the_clock() {
+        let mut manager = manager(10_000, 100, Some(2));
+        manager.record_move(Duration::from_millis(1_000));
+        assert_eq!(
+            manager.control(),
+            TimeControl {
+                remaining: Duration::from_millis(9_100),
+                increment: Duration::from_millis(100),
+                moves_to_go: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn search_stays_within_the_maximum() {
+        use game_tree::games::tic_tac_toe::TicTacToeState;
+
+        let mut manager = manager(1_000, 0, Some(5));
+        let budget = manager.allocate();
+        let elapsed = manager.search(
+            &SearchTree::new(),
+            TicTacToeState::new(),
+            SearchConfig {
+                max_iterations: u64::MAX,
+                ..SearchConfig::default()
+            },
+        );
+
+        assert!(elapsed <= budget.maximum + Duration::from_millis(100));
+        assert_eq!(
+            manager.control().remaining,
+            Duration::from_millis(1_000) - elapsed
+        );
+    }
+
+    #[test]
+    fn search_stops_once_total_visits_decide_the_move() {
+        use game_tree::games::tic_tac_toe::TicTacToeState;
+
+        let root = TicTacToeState::new();
+        let tree = SearchTree::new();
+        tree.search(root.clone(), SearchConfig::default());
+
+        // An earlier search gave the center far more visits than
+        // another search could overtake in the time allocated.
+        let children = match root.calculate_state() {
+            NodeState::HasChildren(children) => children,
+            s => panic!("expected NodeState::HasChildren, got: {:?}", s),
+        };
+        let center = tree.get_metadata(&children[4]).unwrap();
+        while center.number_of_visits() < 1 << 28 {
+            center.merge(&center);
+        }
+
+        let mut manager = manager(60_000, 0, Some(1));
+        let elapsed = manager.search(
+            &tree,
+            root,
+            SearchConfig {
+                max_iterations: u64::MAX,
+                ..SearchConfig::default()
+            },
+        );
+        assert!(elapsed < Duration::from_secs(1));
+    }
+}